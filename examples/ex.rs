@@ -120,15 +120,15 @@ fn main3() {
 
 // fn main2() {
 //     let red = Style {
-//         foreground: Color { r: 255, g: 0, b: 0 },
+//         foreground: Color::Rgb { r: 255, g: 0, b: 0 },
 //         ..Default::default()
 //     };
 //     let green = Style {
-//         foreground: Color { r: 0, g: 255, b: 0 },
+//         foreground: Color::Rgb { r: 0, g: 255, b: 0 },
 //         ..Default::default()
 //     };
 //     let blue = Style {
-//         foreground: Color { r: 0, g: 0, b: 255 },
+//         foreground: Color::Rgb { r: 0, g: 0, b: 255 },
 //         ..Default::default()
 //     };
 
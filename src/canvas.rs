@@ -1,4 +1,5 @@
 use super::*;
+use std::ops::{Bound, Range, RangeBounds};
 
 #[derive(Clone, Default, Debug)]
 pub struct Canvas {
@@ -7,21 +8,399 @@ pub struct Canvas {
 
 impl Canvas {
     pub fn new(rows: Vec<Row>) -> Self {
-        debug_assert!(rows
-            .windows(2)
-            .all(|rows| rows[0].width() == rows[1].width()));
+        debug_assert!(rows.windows(2).all(|rows| {
+            rows[0].width() == rows[1].width() && rows[0].profile() == rows[1].profile()
+        }));
 
         Self { rows }
     }
 
+    /// Returns the rows of this [`Canvas`].
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    /// Returns the height of this [`Canvas`].
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
     /// Returns the width of this [`Canvas`].
     pub fn width(&self) -> u16 {
-        self.rows.get(0).map(|row| row.width()).unwrap_or_default()
+        self.rows.first().map(|row| row.width()).unwrap_or_default()
     }
 
-    pub fn paint(&mut self, line: usize, column: u16, str: &str, style: Style) {
+    /// Returns the [`Profile`] of this [`Canvas`], the one of its rows.
+    pub fn profile(&self) -> Profile {
         self.rows
-            .get_mut(line)
-            .map(|line| line.paint(column, str, style));
+            .first()
+            .map(|row| row.profile().clone())
+            .unwrap_or_default()
+    }
+
+    /// Sets the [`Tabs`] of all rows, for tabs added afterwards.
+    pub fn set_tabs(&mut self, tabs: Tabs) {
+        for row in &mut self.rows {
+            row.set_tabs(tabs.clone());
+        }
+    }
+
+    /// Sets the [`Controls`] policy of all rows, for text added afterwards.
+    pub fn set_controls(&mut self, controls: Controls) {
+        for row in &mut self.rows {
+            row.set_controls(controls);
+        }
+    }
+
+    /// Returns the cell covering `line` and `column`, and whether `column` is the
+    /// second half of this (wide) cell.
+    pub fn cell_at(&self, line: usize, column: u16) -> Option<(row::Cell<'_>, bool)> {
+        self.rows.get(line)?.cell_at(column)
+    }
+
+    /// Resizes the [`Canvas`] to `width` and `height`.
+    ///
+    /// Existing content stays in place, rows are truncated or padded and
+    /// new rows are filled with spaces in `style`.
+    pub fn resize(&mut self, width: u16, height: usize, style: Style) {
+        self.rows.truncate(height);
+
+        for row in &mut self.rows {
+            row.resize(width, style);
+        }
+
+        let mut row = Row::with_profile(" ".repeat(width as usize), style, self.profile());
+        if let Some(first) = self.rows.first() {
+            row.set_tabs(first.tabs().clone());
+            row.set_controls(first.controls());
+        }
+        self.rows.resize(height, row);
+    }
+
+    /// Erases `width` columns at `line` and `column` with spaces in `style`.
+    ///
+    /// Like `EL`, erasing with `u16::MAX` columns erases to the end of the line.
+    pub fn erase(&mut self, line: usize, column: u16, width: u16, style: Style) {
+        if let Some(row) = self.rows.get_mut(line) {
+            row.erase(column, width, style);
+        }
+    }
+
+    /// Erases `line` with spaces in `style`.
+    pub fn erase_line(&mut self, line: usize, style: Style) {
+        if let Some(row) = self.rows.get_mut(line) {
+            row.erase(0, u16::MAX, style);
+        }
+    }
+
+    /// Erases the whole [`Canvas`] with spaces in `style`.
+    pub fn clear(&mut self, style: Style) {
+        for row in &mut self.rows {
+            row.erase(0, u16::MAX, style);
+        }
+    }
+
+    /// Scrolls `lines` up by `n`, filling exposed rows with spaces in `style`.
+    ///
+    /// Rows are moved, not re-created.
+    pub fn scroll_up(&mut self, lines: impl RangeBounds<usize>, n: usize, style: Style) {
+        let lines = self.lines(lines);
+        let rows = &mut self.rows[lines];
+        let n = n.min(rows.len());
+
+        rows.rotate_left(n);
+        let exposed = rows.len() - n;
+        for row in &mut rows[exposed..] {
+            row.erase(0, u16::MAX, style);
+        }
+    }
+
+    /// Scrolls `lines` down by `n`, filling exposed rows with spaces in `style`.
+    ///
+    /// Rows are moved, not re-created.
+    pub fn scroll_down(&mut self, lines: impl RangeBounds<usize>, n: usize, style: Style) {
+        let lines = self.lines(lines);
+        let rows = &mut self.rows[lines];
+        let n = n.min(rows.len());
+
+        rows.rotate_right(n);
+        for row in &mut rows[..n] {
+            row.erase(0, u16::MAX, style);
+        }
+    }
+
+    /// Returns a [`View`] of the rectangle at `line` and `column`, clipped to this [`Canvas`].
+    pub fn view(&mut self, line: usize, column: u16, height: usize, width: u16) -> View<'_> {
+        let height = height.min(self.height().saturating_sub(line));
+        let width = width.min(self.width().saturating_sub(column));
+
+        View {
+            canvas: self,
+            line,
+            column,
+            height,
+            width,
+        }
+    }
+
+    pub fn paint(&mut self, line: usize, column: u16, str: &str, style: Style) {
+        if let Some(row) = self.rows.get_mut(line) {
+            row.paint(column, str, style);
+        }
+    }
+
+    /// Paints `str` at `line` and `column` as a hyperlink to `link`.
+    pub fn paint_link(&mut self, line: usize, column: u16, str: &str, style: Style, link: Link) {
+        if let Some(row) = self.rows.get_mut(line) {
+            row.paint_link(column, str, style, link);
+        }
+    }
+
+    /// Returns the `lines` range, clipped to this [`Canvas`].
+    fn lines(&self, lines: impl RangeBounds<usize>) -> Range<usize> {
+        let start = match lines.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match lines.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.height(),
+        };
+        let end = end.min(self.height());
+
+        start.min(end)..end
+    }
+}
+
+/// A rectangular view in a [`Canvas`].
+///
+/// Coordinates are relative to the view and painting is clipped to its rectangle.
+#[derive(Debug)]
+pub struct View<'a> {
+    canvas: &'a mut Canvas,
+    line: usize,
+    column: u16,
+    height: usize,
+    width: u16,
+}
+
+impl<'a> View<'a> {
+    /// Returns the height of this [`View`].
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the width of this [`View`].
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Returns the [`Profile`] of this [`View`].
+    pub fn profile(&self) -> Profile {
+        self.canvas.profile()
+    }
+
+    /// Returns a [`View`] of the rectangle at `line` and `column`, clipped to this [`View`].
+    ///
+    /// The rectangle is empty when outside this [`View`].
+    pub fn view(&mut self, line: usize, column: u16, height: usize, width: u16) -> View<'_> {
+        View {
+            canvas: self.canvas,
+            line: self.line.saturating_add(line),
+            column: self.column.saturating_add(column),
+            height: height.min(self.height.saturating_sub(line)),
+            width: width.min(self.width.saturating_sub(column)),
+        }
+    }
+
+    /// Paints `str` at `line` and `column`, clipped to this [`View`].
+    ///
+    /// A wide grapheme straddling the right border is replaced by a space.
+    pub fn paint(&mut self, line: usize, column: u16, str: &str, style: Style) {
+        // Nothing to do outside the `View`
+        if line >= self.height || column >= self.width {
+            return;
+        }
+
+        let (line, available) = (self.line.saturating_add(line), self.width - column);
+        let column = self.column.saturating_add(column);
+        let profile = self.profile();
+        let row = &self.canvas.rows[line];
+        let str = row.controls().sanitize(str);
+        let str = &*row.tabs().expand(&str, column, &profile);
+        let (cropped, width) = line::cell::crop(str, available, &profile);
+
+        // The next cell is wide and does not fit
+        if width < available
+            && line::cell::Cells::with_profile(&str[cropped.len()..], &profile)
+                .next()
+                .is_some()
+        {
+            self.canvas
+                .paint(line, column, &format!("{cropped} "), style);
+        } else {
+            self.canvas.paint(line, column, cropped, style);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const RED: Style = Style {
+        foreground: Color::Ansi(Ansi::Red),
+        background: Color::Default,
+        attributes: Attributes::empty(),
+        underline: None,
+    };
+
+    pub(crate) fn strs(canvas: &Canvas) -> Vec<String> {
+        canvas
+            .rows()
+            .iter()
+            .map(|row| row.cells().map(|cell| cell.str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn cell_at() {
+        let mut canvas = Canvas::new(vec![Row::new("abcd".into(), default()); 2]);
+        canvas.paint(1, 1, "🦀", RED);
+
+        let cell_at = |line, column| {
+            canvas
+                .cell_at(line, column)
+                .map(|(cell, half)| (cell.str(), cell.column(), cell.style(), half))
+        };
+
+        assert_eq!(cell_at(0, 0), Some(("a", 0, default(), false)));
+        assert_eq!(cell_at(1, 0), Some(("a", 0, default(), false)));
+        assert_eq!(cell_at(1, 1), Some(("🦀", 1, RED, false)));
+        assert_eq!(cell_at(1, 2), Some(("🦀", 1, RED, true)));
+        assert_eq!(cell_at(1, 3), Some(("d", 3, default(), false)));
+        assert_eq!(cell_at(1, 4), None);
+        assert_eq!(cell_at(2, 0), None);
+    }
+
+    #[test]
+    fn erase() {
+        let mut canvas = Canvas::new(vec![Row::new("a🦀b🦀".into(), RED); 3]);
+
+        canvas.erase(0, 2, u16::MAX, default());
+        canvas.erase(1, 0, 2, default());
+        canvas.erase_line(2, default());
+        assert_eq!(strs(&canvas), ["a     ", "   b🦀", "      "]);
+
+        let styles = |line: usize| {
+            canvas.rows()[line]
+                .cells()
+                .map(|cell| cell.style() == RED)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(styles(0), [true, true, false, false, false, false]);
+        assert_eq!(styles(1), [false, false, true, true, true]);
+
+        canvas.clear(RED);
+        assert_eq!(strs(&canvas), ["      "; 3]);
+        assert!(canvas
+            .rows()
+            .iter()
+            .all(|row| row.cells().all(|cell| cell.style() == RED)));
+    }
+
+    #[test]
+    fn scroll() {
+        let rows = ["0", "1", "2", "3", "4"];
+        let mut canvas = Canvas::new(
+            rows.iter()
+                .map(|str| Row::new(str.to_string(), default()))
+                .collect(),
+        );
+
+        canvas.scroll_up(.., 2, default());
+        assert_eq!(strs(&canvas), ["2", "3", "4", " ", " "]);
+
+        canvas.scroll_down(1..4, 1, default());
+        assert_eq!(strs(&canvas), ["2", " ", "3", "4", " "]);
+
+        canvas.scroll_up(3..=9, 9, default());
+        assert_eq!(strs(&canvas), ["2", " ", "3", " ", " "]);
+
+        canvas.scroll_down(7.., 1, default());
+        assert_eq!(strs(&canvas), ["2", " ", "3", " ", " "]);
+    }
+
+    #[test]
+    fn profile() {
+        let mut canvas = Canvas::new(vec![Row::with_profile(
+            "±±".into(),
+            default(),
+            Profile::Wide,
+        )]);
+        assert_eq!((canvas.profile(), canvas.width()), (Profile::Wide, 4));
+
+        canvas.resize(5, 2, default());
+        assert_eq!(canvas.rows()[1].profile(), &Profile::Wide);
+
+        let mut view = canvas.view(1, 0, 1, 3);
+        view.paint(0, 0, "α±", default());
+        assert_eq!(strs(&canvas), ["±± ", "α   "]);
+    }
+
+    #[test]
+    fn view() {
+        let mut canvas = Canvas::new(vec![Row::new("......".into(), default()); 4]);
+
+        let mut view = canvas.view(1, 1, 2, 4);
+        view.paint(0, 0, "abcdef", default());
+        view.paint(1, 2, "🦀🦀", default());
+        view.paint(2, 0, "out", default());
+        view.paint(0, 4, "out", default());
+
+        let mut nested = view.view(1, 1, 5, 1);
+        assert_eq!((nested.height(), nested.width()), (1, 1));
+        nested.paint(0, 0, "x", default());
+
+        assert_eq!(strs(&canvas), ["......", ".abcd.", "..x🦀.", "......"]);
+
+        let mut view = canvas.view(0, 4, 1, 5);
+        assert_eq!((view.height(), view.width()), (1, 2));
+        view.paint(0, 1, "🦀", default());
+
+        assert_eq!(strs(&canvas)[0], "..... ");
+
+        let mut view = canvas.view(3, 1, 1, 4);
+        view.paint(0, 0, "a\x1b\tb", default());
+        assert_eq!(strs(&canvas)[3], ".a   .");
+
+        canvas.set_controls(Controls::Caret);
+        canvas.set_tabs(Tabs::Every(4));
+        let mut view = canvas.view(3, 1, 1, 4);
+        view.paint(0, 0, "\x1b\tb", default());
+        assert_eq!(strs(&canvas)[3], ".^[ b.");
+    }
+
+    #[test]
+    fn view_overflow() {
+        let mut canvas = Canvas::new(vec![Row::new("......".into(), default()); 4]);
+        let mut view = canvas.view(1, 1, 2, 4);
+
+        let mut nested = view.view(usize::MAX, u16::MAX, usize::MAX, u16::MAX);
+        assert_eq!((nested.height(), nested.width()), (0, 0));
+        nested.paint(0, 0, "x", default());
+
+        let mut nested = nested.view(1, 1, 1, 1);
+        assert_eq!((nested.height(), nested.width()), (0, 0));
+        nested.paint(usize::MAX, u16::MAX, "x", default());
+
+        let mut nested = view.view(1, 3, usize::MAX, u16::MAX);
+        assert_eq!((nested.height(), nested.width()), (1, 1));
+        nested.paint(0, 0, "x", default());
+
+        assert_eq!(strs(&canvas), ["......", "......", "....x.", "......"]);
     }
 }
@@ -0,0 +1,207 @@
+use super::*;
+use std::sync::OnceLock;
+
+/// The color depth of a terminal.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Default, Debug)]
+pub enum Depth {
+    /// No colors, only attributes.
+    Monochrome,
+    /// The 16 named colors.
+    Ansi,
+    /// The 256-color palette.
+    Indexed,
+    /// Truecolors.
+    #[default]
+    TrueColor,
+}
+
+impl Depth {
+    /// Detects the color depth from the values of the `COLORTERM` and `TERM`
+    /// environment variables.
+    pub fn detect(colorterm: Option<&str>, term: Option<&str>) -> Self {
+        if let Some("truecolor" | "24bit") = colorterm {
+            return Self::TrueColor;
+        }
+
+        match term {
+            None | Some("" | "dumb") => Self::Monochrome,
+            Some(term) if term.ends_with("-direct") => Self::TrueColor,
+            Some(term) if term.contains("256color") => Self::Indexed,
+            Some(_) => Self::Ansi,
+        }
+    }
+
+    /// Detects the color depth from the environment.
+    pub fn from_env() -> Self {
+        let colorterm = std::env::var("COLORTERM").ok();
+        let term = std::env::var("TERM").ok();
+
+        Self::detect(colorterm.as_deref(), term.as_deref())
+    }
+}
+
+impl Color {
+    /// Converts this [`Color`] to the nearest color displayable with `depth`.
+    ///
+    /// Colors are compared perceptually (in the Oklab color space).
+    /// Named colors are approximated with xterm's default palette.
+    pub fn to_depth(self, depth: Depth) -> Self {
+        match (depth, self) {
+            (Depth::TrueColor, _) | (_, Self::Default) => self,
+            (Depth::Monochrome, _) => Self::Default,
+            (Depth::Indexed, Self::Ansi(_) | Self::Indexed(_)) => self,
+            (Depth::Indexed, Self::Rgb { r, g, b }) => Self::Indexed(nearest(16..=255, (r, g, b))),
+            (Depth::Ansi, Self::Ansi(_)) => self,
+            (Depth::Ansi, Self::Indexed(index)) => match Ansi::from_index(index) {
+                Some(ansi) => Self::Ansi(ansi),
+                None => Self::Ansi(Ansi::ALL[nearest(0..=15, rgb(index)) as usize]),
+            },
+            (Depth::Ansi, Self::Rgb { r, g, b }) => {
+                Self::Ansi(Ansi::ALL[nearest(0..=15, (r, g, b)) as usize])
+            }
+        }
+    }
+}
+
+impl Style {
+    /// Converts the colors of this [`Style`] to `depth`.
+    ///
+    /// Attributes are kept, even in [`Depth::Monochrome`].
+    pub fn to_depth(self, depth: Depth) -> Self {
+        Self {
+            foreground: self.foreground.to_depth(depth),
+            background: self.background.to_depth(depth),
+            underline: self.underline.map(|underline| Underline {
+                color: underline.color.to_depth(depth),
+                ..underline
+            }),
+            ..self
+        }
+    }
+}
+
+/// Returns the RGB value of `index` in xterm's default 256-color palette.
+fn rgb(index: u8) -> (u8, u8, u8) {
+    const ANSI: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+    const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+    match index {
+        0..=15 => ANSI[index as usize],
+        16..=231 => {
+            let index = index - 16;
+            let level = |i: u8| CUBE[i as usize];
+
+            (level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        232..=255 => {
+            let gray = 8 + 10 * (index - 232);
+
+            (gray, gray, gray)
+        }
+    }
+}
+
+/// The palette colors in the Oklab color space, computed once.
+static PALETTE: OnceLock<[[f64; 3]; 256]> = OnceLock::new();
+
+/// Returns the palette index in `indexes` perceptually nearest to `color`.
+fn nearest(indexes: std::ops::RangeInclusive<u8>, color: (u8, u8, u8)) -> u8 {
+    let palette = PALETTE.get_or_init(|| std::array::from_fn(|index| oklab(rgb(index as u8))));
+    let color = oklab(color);
+    let distance = |index: u8| {
+        let [l, a, b] = palette[index as usize];
+        let [dl, da, db] = [l - color[0], a - color[1], b - color[2]];
+
+        dl * dl + da * da + db * db
+    };
+
+    indexes
+        .map(|index| (index, distance(index)))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(index, _)| index)
+        .expect("non-empty palette")
+}
+
+/// Converts an sRGB color to the Oklab color space.
+fn oklab((r, g, b): (u8, u8, u8)) -> [f64; 3] {
+    fn linear(c: u8) -> f64 {
+        let c = c as f64 / 255.;
+
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    }
+
+    let (r, g, b) = (linear(r), linear(g), linear(b));
+
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(None               , None                  => Depth::Monochrome; "nothing")]
+    #[test_case(None               , Some("dumb")          => Depth::Monochrome; "dumb")]
+    #[test_case(None               , Some("xterm")         => Depth::Ansi      ; "xterm")]
+    #[test_case(None               , Some("linux")         => Depth::Ansi      ; "linux")]
+    #[test_case(None               , Some("tmux-256color") => Depth::Indexed   ; "tmux")]
+    #[test_case(None               , Some("xterm-direct")  => Depth::TrueColor ; "direct")]
+    #[test_case(Some("truecolor")  , Some("xterm-256color")=> Depth::TrueColor ; "truecolor")]
+    #[test_case(Some("24bit")      , None                  => Depth::TrueColor ; "24bit")]
+    #[test_case(Some("yes")        , Some("screen")        => Depth::Ansi      ; "other")]
+    fn detect(colorterm: Option<&str>, term: Option<&str>) -> Depth {
+        Depth::detect(colorterm, term)
+    }
+
+    #[test]
+    fn palette() {
+        assert_eq!(rgb(16), (0, 0, 0));
+        assert_eq!(rgb(196), (255, 0, 0));
+        assert_eq!(rgb(231), (255, 255, 255));
+        assert_eq!(rgb(232), (8, 8, 8));
+        assert_eq!(rgb(255), (238, 238, 238));
+    }
+
+    #[test_case(Color::Rgb { r: 255, g: 0, b: 0 }    , Depth::Indexed    => Color::Indexed(196)           ; "red to indexed")]
+    #[test_case(Color::Rgb { r: 250, g: 250, b: 250 }, Depth::Indexed    => Color::Indexed(231)           ; "white to indexed")]
+    #[test_case(Color::Rgb { r: 128, g: 128, b: 128 }, Depth::Indexed    => Color::Indexed(244)           ; "gray to indexed")]
+    #[test_case(Color::Rgb { r: 255, g: 0, b: 0 }    , Depth::Ansi       => Color::Ansi(Ansi::BrightRed)  ; "red to ansi")]
+    #[test_case(Color::Rgb { r: 10, g: 10, b: 10 }   , Depth::Ansi       => Color::Ansi(Ansi::Black)      ; "black to ansi")]
+    #[test_case(Color::Indexed(4)                    , Depth::Ansi       => Color::Ansi(Ansi::Blue)       ; "named index to ansi")]
+    #[test_case(Color::Indexed(46)                   , Depth::Ansi       => Color::Ansi(Ansi::BrightGreen); "index to ansi")]
+    #[test_case(Color::Ansi(Ansi::Cyan)              , Depth::Indexed    => Color::Ansi(Ansi::Cyan)       ; "ansi to indexed")]
+    #[test_case(Color::Rgb { r: 1, g: 2, b: 3 }      , Depth::TrueColor  => Color::Rgb { r: 1, g: 2, b: 3 }; "truecolor")]
+    #[test_case(Color::Indexed(46)                   , Depth::Monochrome => Color::Default                ; "monochrome")]
+    fn to_depth(color: Color, depth: Depth) -> Color {
+        color.to_depth(depth)
+    }
+}
@@ -0,0 +1,480 @@
+use super::*;
+use std::borrow::Cow;
+use std::ops::Range;
+use unicode_linebreak::{linebreaks, BreakOpportunity};
+
+/// The horizontal alignment of a [`Paragraph`].
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+    /// Stretches spaces to fill wrapped lines, except the last of each paragraph.
+    Justify,
+}
+
+/// Styled text wrapped at line-break opportunities.
+///
+/// Newlines are hard line breaks. Other control characters are sanitized, and tabs
+/// expand to spaces from the start of their line (before wrapping), when wrapped.
+#[derive(Clone, Eq, PartialEq, Default, Debug)]
+pub struct Paragraph {
+    text: String,
+    /// Byte ranges of `text` and their styles.
+    styles: Vec<(Range<usize>, Style)>,
+    align: Align,
+    tabs: Tabs,
+    controls: Controls,
+}
+
+impl Paragraph {
+    /// Returns an empty [`Paragraph`] with `align`.
+    pub fn new(align: Align) -> Self {
+        Self { align, ..default() }
+    }
+
+    /// Sets the [`Tabs`] of this [`Paragraph`].
+    pub fn set_tabs(&mut self, tabs: Tabs) {
+        self.tabs = tabs;
+    }
+
+    /// Sets the [`Controls`] policy of this [`Paragraph`].
+    pub fn set_controls(&mut self, controls: Controls) {
+        self.controls = controls;
+    }
+
+    /// Pushes `str` in `style`.
+    pub fn push(&mut self, str: &str, style: Style) {
+        let start = self.text.len();
+        self.text.push_str(str);
+        self.styles.push((start..self.text.len(), style));
+    }
+
+    /// Returns the number of lines of this [`Paragraph`] wrapped to `width`, measured
+    /// with `profile`.
+    pub fn lines(&self, width: u16, profile: &Profile) -> usize {
+        self.sanitize(profile).wrap(width, profile).len()
+    }
+
+    /// Paints this [`Paragraph`] wrapped to the width of `view`.
+    ///
+    /// Returns the number of lines, including those not fitting in `view`.
+    pub fn paint(&self, view: &mut View<'_>) -> usize {
+        let profile = view.profile();
+        self.sanitize(&profile).paint_sanitized(view, &profile)
+    }
+
+    /// Paints this sanitized [`Paragraph`] wrapped to the width of `view`.
+    fn paint_sanitized(&self, view: &mut View<'_>, profile: &Profile) -> usize {
+        let lines = self.wrap(view.width(), profile);
+
+        for (line, wrapped) in lines.iter().enumerate().take(view.height()) {
+            let extra = view.width() - wrapped.width;
+            let gaps = wrapped.words.len().saturating_sub(1) as u16;
+            let (mut column, justify) = match self.align {
+                Align::Left => (0, false),
+                Align::Center => (extra / 2, false),
+                Align::Right => (extra, false),
+                Align::Justify => (0, !wrapped.hard && gaps > 0),
+            };
+
+            for (i, word) in wrapped.words.iter().enumerate() {
+                column = self.paint_range(view, line, column, word.text.clone(), profile);
+
+                // Spaces after the last word are not displayed
+                if i as u16 == gaps {
+                    break;
+                }
+
+                let mut spaces = word.spaces_width;
+                if justify {
+                    // Spread the extra columns, the first gaps are wider
+                    spaces += extra / gaps + ((i as u16) < extra % gaps) as u16;
+                }
+
+                let style = self.style(word.spaces.start);
+                view.paint(line, column, &" ".repeat(spaces as usize), style);
+                column += spaces;
+            }
+        }
+
+        lines.len()
+    }
+
+    /// Paints `range` of the text at `line` and `column`, and returns the next column.
+    fn paint_range(
+        &self,
+        view: &mut View<'_>,
+        line: usize,
+        column: u16,
+        range: Range<usize>,
+        profile: &Profile,
+    ) -> u16 {
+        let mut column = column;
+
+        for (styled, style) in &self.styles {
+            let (start, end) = (styled.start.max(range.start), styled.end.min(range.end));
+
+            if start < end {
+                let str = &self.text[start..end];
+                view.paint(line, column, str, *style);
+                column += width_of(str, profile);
+            }
+        }
+
+        column
+    }
+
+    /// Returns the style at `index` in the text.
+    fn style(&self, index: usize) -> Style {
+        self.styles
+            .iter()
+            .find(|(range, _)| range.contains(&index))
+            .map(|(_, style)| *style)
+            .unwrap_or_default()
+    }
+
+    /// Returns a copy of this [`Paragraph`] with sanitized control characters and
+    /// tabs expanded from the start of their line, measured with `profile`.
+    fn sanitize(&self, profile: &Profile) -> Self {
+        let mut sanitized = Self {
+            text: String::with_capacity(self.text.len()),
+            styles: Vec::with_capacity(self.styles.len()),
+            align: self.align,
+            tabs: self.tabs.clone(),
+            controls: self.controls,
+        };
+        let mut column = 0;
+
+        for (range, style) in &self.styles {
+            let start = sanitized.text.len();
+
+            for (i, line) in self.text[range.clone()].split('\n').enumerate() {
+                if i > 0 {
+                    sanitized.text.push('\n');
+                    column = 0;
+                }
+
+                let line = self.controls.sanitize(line);
+                let line = self.tabs.expand(&line, column, profile);
+                column = column.saturating_add(width_of(&line, profile));
+                sanitized.text.push_str(&line);
+            }
+
+            sanitized.styles.push((start..sanitized.text.len(), *style));
+        }
+
+        sanitized
+    }
+
+    /// Wraps the (sanitized) text to `width`, measured with `profile`.
+    fn wrap(&self, width: u16, profile: &Profile) -> Vec<Wrapped> {
+        let mut lines = Vec::new();
+
+        // Nothing fits in no columns
+        if width == 0 {
+            return lines;
+        }
+
+        let mut line = Wrapped::default();
+        let mut start = 0;
+
+        for (end, opportunity) in linebreaks(&self.text) {
+            let piece = &self.text[start..end];
+            let trimmed = start + piece.trim_end().len();
+            let mut word = Word {
+                text: start..trimmed,
+                width: width_of(&self.text[start..trimmed], profile),
+                spaces: trimmed..end,
+                spaces_width: width_of(&self.text[trimmed..end], profile),
+            };
+            start = end;
+
+            // Soft wrap when the word does not fit
+            if !line.words.is_empty() && line.end() + word.width > width {
+                lines.push(std::mem::take(&mut line));
+            }
+
+            // Break words wider than the line at graphemes
+            while word.width > width {
+                let text = &self.text[word.text.clone()];
+                let (mut head, mut head_width) = line::cell::crop(text, width, profile);
+                let mut taken = head_width;
+
+                // A grapheme wider than the line is painted as a blank cell
+                if head_width == 0 {
+                    let cell = line::cell::Cells::with_profile(text, profile).next();
+                    let cell = cell.expect("a word wider than the line has cells");
+                    let end = cell.index + cell.str.len();
+
+                    // The grapheme is the rest of the word
+                    if end == text.len() {
+                        word.width = width;
+                        break;
+                    }
+
+                    (head, head_width, taken) = (&text[..end], width, cell.width);
+                }
+
+                let head = word.text.start..word.text.start + head.len();
+
+                lines.push(Wrapped {
+                    words: vec![Word {
+                        text: head.clone(),
+                        width: head_width,
+                        spaces: head.end..head.end,
+                        spaces_width: 0,
+                    }],
+                    width: head_width,
+                    hard: false,
+                });
+                word.text.start = head.end;
+                word.width -= taken;
+            }
+
+            if word.width > 0 || !word.text.is_empty() || line.words.is_empty() {
+                line.width = line.end() + word.width;
+                line.words.push(word);
+            }
+
+            if opportunity == BreakOpportunity::Mandatory {
+                line.hard = true;
+                lines.push(std::mem::take(&mut line));
+            }
+        }
+
+        lines
+    }
+}
+
+/// The default ellipsis of [`truncate`].
+pub const ELLIPSIS: &str = "…";
+
+/// Where [`truncate`] cuts text.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub enum Truncation {
+    Start,
+    Middle,
+    #[default]
+    End,
+}
+
+/// Truncates `str` to `width` measured with `profile`, replacing the cut text with
+/// `ellipsis`.
+///
+/// Grapheme clusters are never split: the result can be narrower than `width`
+/// when a wide grapheme does not fit. An `ellipsis` wider than `width` is cropped.
+pub fn truncate<'a>(
+    str: &'a str,
+    width: u16,
+    truncation: Truncation,
+    ellipsis: &str,
+    profile: &Profile,
+) -> Cow<'a, str> {
+    let total = width_of(str, profile);
+
+    // Nothing to do when `str` fits
+    if total <= width {
+        return Cow::Borrowed(str);
+    }
+
+    let (ellipsis, ellipsis_width) = line::cell::crop(ellipsis, width, profile);
+    let available = width - ellipsis_width;
+
+    // The index of the first cell starting at or after `column`
+    let from = |column: u16| {
+        unicode::Cells::with_profile(str, profile)
+            .find(|cell| cell.column as u16 >= column)
+            .map(|cell| cell.index)
+            .unwrap_or(str.len())
+    };
+
+    Cow::Owned(match truncation {
+        Truncation::Start => [ellipsis, &str[from(total - available)..]].concat(),
+        Truncation::Middle => {
+            let (head, head_width) = line::cell::crop(str, available.div_ceil(2), profile);
+            let tail = &str[from(total - (available - head_width))..];
+
+            [head, ellipsis, tail].concat()
+        }
+        Truncation::End => [line::cell::crop(str, available, profile).0, ellipsis].concat(),
+    })
+}
+
+/// A line of a wrapped [`Paragraph`].
+#[derive(Clone, Eq, PartialEq, Default, Debug)]
+struct Wrapped {
+    words: Vec<Word>,
+    /// The width without trailing spaces.
+    width: u16,
+    /// Whether the line ends with a hard line break.
+    hard: bool,
+}
+
+impl Wrapped {
+    /// Returns the column where the next word starts.
+    fn end(&self) -> u16 {
+        self.width
+            + self
+                .words
+                .last()
+                .map(|word| word.spaces_width)
+                .unwrap_or_default()
+    }
+}
+
+/// A word of a [`Wrapped`] line, with its trailing spaces.
+#[derive(Clone, Eq, PartialEq, Default, Debug)]
+struct Word {
+    text: Range<usize>,
+    width: u16,
+    spaces: Range<usize>,
+    spaces_width: u16,
+}
+
+/// Returns the width of `str` in cells, measured with `profile`.
+fn width_of(str: &str, profile: &Profile) -> u16 {
+    unicode::Cells::with_profile(str, profile)
+        .map(|cell| cell.width as u16)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::tests::strs;
+    use test_case::test_case;
+
+    const RED: Style = Style {
+        foreground: Color::Ansi(Ansi::Red),
+        background: Color::Default,
+        attributes: Attributes::empty(),
+        underline: None,
+    };
+
+    fn layout(text: &str, align: Align, width: u16) -> (usize, Vec<String>) {
+        let mut paragraph = Paragraph::new(align);
+        paragraph.push(text, default());
+
+        let mut canvas = Canvas::new(vec![Row::new(".".repeat(width as usize), default()); 4]);
+        let lines = paragraph.paint(&mut canvas.view(0, 0, 4, width));
+
+        assert_eq!(paragraph.lines(width, &Profile::Narrow), lines);
+        (lines, strs(&canvas))
+    }
+
+    #[test_case("", 8, 0 => vec!["........", "........", "........", "........"]; "Empty")]
+    #[test_case("hello world", 8, 2 => vec!["hello...", "world...", "........", "........"]; "Wrap")]
+    #[test_case("hello world", 11, 1 => vec!["hello world", "...........", "...........", "..........."]; "Fit")]
+    #[test_case("a\n\nb\n", 3, 3 => vec!["a..", "...", "b..", "..."]; "Newlines")]
+    #[test_case("abcdefghij", 4, 3 => vec!["abcd", "efgh", "ij..", "...."]; "Long word")]
+    #[test_case("ab 🦀🦀🦀", 3, 4 => vec!["ab.", "🦀.", "🦀.", "🦀."]; "Wide")]
+    #[test_case("a b c d e f g h i j", 3, 5 => vec!["a b", "c d", "e f", "g h"]; "Overflow")]
+    #[test_case("a\tb\n\tc", 10, 2 => vec!["a       b.", "        c.", "..........", ".........."]; "Tabs")]
+    #[test_case("🦀🦀 a", 1, 3 => vec![" ", " ", "a", "."]; "Grapheme wider than the line")]
+    fn left(text: &str, width: u16, lines: usize) -> Vec<String> {
+        let (count, strs) = layout(text, Align::Left, width);
+        assert_eq!(count, lines);
+
+        strs
+    }
+
+    #[test]
+    fn align() {
+        let text = "one two three four";
+
+        assert_eq!(
+            layout(text, Align::Center, 9).1,
+            [".one two.", "..three..", "..four...", "........."]
+        );
+        assert_eq!(
+            layout(text, Align::Right, 9).1,
+            ["..one two", "....three", ".....four", "........."]
+        );
+        assert_eq!(
+            layout("aa b c dd\nlast", Align::Justify, 8).1,
+            ["aa  b  c", "dd......", "last....", "........"]
+        );
+    }
+
+    #[test_case("abcdef", 6, Truncation::End,    "…"   => "abcdef"; "Fit")]
+    #[test_case("abcdef", 4, Truncation::End,    "…"   => "abc…"; "End")]
+    #[test_case("abcdef", 4, Truncation::Start,  "…"   => "…def"; "Start")]
+    #[test_case("abcdef", 4, Truncation::Middle, "…"   => "ab…f"; "Middle")]
+    #[test_case("abcdef", 5, Truncation::Middle, "..." => "a...f"; "Custom ellipsis")]
+    #[test_case("abcdef", 2, Truncation::End,    "..." => ".."; "Wide ellipsis")]
+    #[test_case("🦀🦀🦀", 4, Truncation::End,    "…"   => "🦀…"; "Wide end")]
+    #[test_case("🦀🦀🦀", 4, Truncation::Start,  "…"   => "…🦀"; "Wide start")]
+    #[test_case("🦀🦀🦀", 5, Truncation::Middle, "…"   => "🦀…🦀"; "Wide middle")]
+    #[test_case("🦀a🦀", 4, Truncation::Middle,  "…"   => "🦀…"; "Wide middle cut")]
+    #[test_case("e\u{301}e\u{301}e\u{301}", 2, Truncation::End, "…" => "e\u{301}…"; "Combining")]
+    fn truncation(str: &str, width: u16, truncation: Truncation, ellipsis: &str) -> String {
+        let truncated = truncate(str, width, truncation, ellipsis, &Profile::Narrow);
+        assert!(width_of(&truncated, &Profile::Narrow) <= width);
+
+        truncated.into_owned()
+    }
+
+    #[test]
+    fn tabs() {
+        let mut paragraph = Paragraph::new(Align::Left);
+        paragraph.set_tabs(Tabs::Every(4));
+        paragraph.push("a", default());
+        paragraph.push("\tb", default());
+
+        let mut canvas = Canvas::new(vec![Row::new("....".into(), default()); 2]);
+        assert_eq!(paragraph.lines(4, &Profile::Narrow), 2);
+        assert_eq!(paragraph.paint(&mut canvas.view(0, 0, 2, 4)), 2);
+        assert_eq!(strs(&canvas), ["a...", "b..."]);
+
+        // Tabs expand with the profile of the view
+        let mut paragraph = Paragraph::new(Align::Left);
+        paragraph.set_tabs(Tabs::Every(4));
+        paragraph.push("±\tb", default());
+
+        let row = Row::with_profile("......".into(), default(), Profile::Wide);
+        let mut canvas = Canvas::new(vec![row]);
+        assert_eq!(paragraph.lines(6, &Profile::Wide), 1);
+        assert_eq!(paragraph.paint(&mut canvas.view(0, 0, 1, 6)), 1);
+        assert_eq!(strs(&canvas), ["±  b."]);
+    }
+
+    #[test]
+    fn controls() {
+        let mut paragraph = Paragraph::new(Align::Left);
+        paragraph.set_controls(Controls::Caret);
+        paragraph.push("a\x1b\tb", default());
+
+        let mut canvas = Canvas::new(vec![Row::new("..........".into(), default())]);
+        assert_eq!(paragraph.lines(10, &Profile::Narrow), 1);
+        assert_eq!(paragraph.paint(&mut canvas.view(0, 0, 1, 10)), 1);
+        assert_eq!(strs(&canvas), ["a^[     b."]);
+    }
+
+    #[test]
+    fn styles() {
+        let mut paragraph = Paragraph::new(Align::Left);
+        paragraph.push("ab ", default());
+        paragraph.push("cd", RED);
+
+        let mut canvas = Canvas::new(vec![Row::new("......".into(), default())]);
+        paragraph.paint(&mut canvas.view(0, 0, 1, 6));
+
+        let styles = canvas.rows()[0]
+            .cells()
+            .map(|cell| (cell.str().to_string(), cell.style() == RED))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            styles,
+            [
+                ("a".into(), false),
+                ("b".into(), false),
+                (" ".into(), false),
+                ("c".into(), true),
+                ("d".into(), true),
+                (".".into(), false),
+            ]
+        );
+    }
+}
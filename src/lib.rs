@@ -1,14 +1,22 @@
 #![allow(unused)]
 
 pub mod canvas;
+pub mod depth;
+pub mod layout;
 pub mod line;
+pub mod parse;
+pub mod render;
 pub mod row;
 pub mod spans;
 pub mod style;
 pub mod unicode;
 
 use canvas::*;
+use depth::*;
+use layout::*;
 use line::*;
+use parse::*;
+use render::*;
 use row::*;
 use spans::*;
 use style::*;
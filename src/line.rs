@@ -1,46 +1,258 @@
 use super::*;
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::Arc;
 use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete, Graphemes, UnicodeSegmentation};
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-fn width(str: &str) -> u16 {
-    UnicodeSegmentation::graphemes(str, true)
-        .map(|grapheme| unicode_width::UnicodeWidthStr::width(grapheme).min(2) as u16)
-        .sum()
+fn width(str: &str, profile: &Profile) -> u16 {
+    profile.width(str) as u16
 }
 
-#[derive(Clone, Eq, PartialEq, Default, Debug)]
+/// Returns whether `before` and `after` cannot join into a grapheme.
+///
+/// Line boundaries and zero-width non-joiners (`\u{200C}`) never join.
+fn breaks(before: &str, after: &str) -> bool {
+    if before.is_empty() || after.is_empty() || before.ends_with(ZWNJ) || after.starts_with(ZWNJ) {
+        return true;
+    }
+
+    is_boundary(before, after)
+}
+
+/// Returns whether there is a grapheme boundary between `before` and `after`.
+fn is_boundary(before: &str, after: &str) -> bool {
+    let at = before.len();
+    let mut cursor = GraphemeCursor::new(at, at + after.len(), true);
+
+    loop {
+        match cursor.is_boundary(after, at) {
+            Ok(is_boundary) => return is_boundary,
+            Err(GraphemeIncomplete::PreContext(end)) => cursor.provide_context(&before[..end], 0),
+            // We gave the whole string to the cursor
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Replaces the first grapheme of `str` with spaces of the same width when it cannot
+/// start a grapheme, i.e. when it would extend any previous grapheme.
+///
+/// Zero-width non-joiners (`\u{200C}`) are extending characters, and cannot prevent
+/// extending characters, joiners or spacing marks to join.
+fn detach<'a>(str: &'a str, profile: &Profile) -> Cow<'a, str> {
+    if str.is_empty() || is_boundary(ZWNJ.encode_utf8(&mut [0; 4]), str) {
+        return Cow::Borrowed(str);
+    }
+
+    let grapheme = UnicodeSegmentation::graphemes(str, true)
+        .next()
+        .unwrap_or_default();
+    let spaces = " ".repeat(grapheme_width(grapheme, profile));
+
+    Cow::Owned(spaces + &str[grapheme.len()..])
+}
+
+/// Tab stops, where tabs (`\t`) expand to.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Tabs {
+    /// A tab stop every `n` columns.
+    Every(u16),
+    /// Custom tab stops, in increasing order.
+    ///
+    /// Tabs after the last stop expand to a single space.
+    Stops(Arc<[u16]>),
+}
+
+impl Tabs {
+    /// Returns the next tab stop after `column`, at most `u16::MAX`.
+    pub fn next(&self, column: u16) -> u16 {
+        match self {
+            Self::Every(0) => column.saturating_add(1),
+            Self::Every(n) => (column / n).saturating_add(1).saturating_mul(*n),
+            Self::Stops(stops) => stops
+                .iter()
+                .copied()
+                .find(|stop| *stop > column)
+                .unwrap_or(column.saturating_add(1)),
+        }
+    }
+
+    /// Expands the tabs of `str`, starting at `column`, to spaces.
+    pub fn expand<'a>(&self, str: &'a str, column: u16, profile: &Profile) -> Cow<'a, str> {
+        // Nothing to do without tabs
+        if !str.contains('\t') {
+            return Cow::Borrowed(str);
+        }
+
+        let mut expanded = String::with_capacity(str.len());
+        let mut column = column;
+
+        for grapheme in UnicodeSegmentation::graphemes(str, true) {
+            if grapheme == "\t" {
+                let next = self.next(column);
+                expanded.push_str(&" ".repeat((next - column) as usize));
+                column = next;
+            } else {
+                expanded.push_str(grapheme);
+                column = column.saturating_add(grapheme_width(grapheme, profile) as u16);
+            }
+        }
+
+        Cow::Owned(expanded)
+    }
+}
+
+impl Default for Tabs {
+    fn default() -> Self {
+        Self::Every(8)
+    }
+}
+
+/// What to do with control characters (`\x1b`, `\r`, `\n`, C1 controls, ...).
+///
+/// Tabs are kept, and expanded afterwards (see [`Tabs`]).
+///
+/// Lines strip control characters by default, so that text cannot smuggle escape
+/// sequences to the terminal.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Default, Debug)]
+pub enum Controls {
+    /// Removes them.
+    #[default]
+    Strip,
+    /// Replaces them with their caret notation (e.g. `^[` for `\x1b`).
+    ///
+    /// C1 controls are shown as their escape sequence (e.g. `^[[` for `\u{9B}`).
+    Caret,
+    /// Replaces them with U+FFFD (`�`).
+    Replace,
+}
+
+impl Controls {
+    /// Sanitizes the control characters of `str`, except tabs.
+    pub fn sanitize<'a>(&self, str: &'a str) -> Cow<'a, str> {
+        let control = |char: char| char.is_control() && char != '\t';
+
+        // Nothing to do without controls
+        if !str.chars().any(control) {
+            return Cow::Borrowed(str);
+        }
+
+        let mut sanitized = String::with_capacity(str.len());
+
+        for char in str.chars() {
+            match (self, char as u32) {
+                (_, _) if !control(char) => sanitized.push(char),
+                (Self::Strip, _) => {}
+                (Self::Replace, _) => sanitized.push(char::REPLACEMENT_CHARACTER),
+                (Self::Caret, code @ (0x00..=0x1F | 0x7F)) => {
+                    sanitized.push('^');
+                    sanitized.push((code as u8 ^ 0x40) as char);
+                }
+                (Self::Caret, code) => {
+                    sanitized.push_str("^[");
+                    sanitized.push((code as u8 - 0x40) as char);
+                }
+            }
+        }
+
+        Cow::Owned(sanitized)
+    }
+}
+
+/// Columns between two checkpoints of the [`Line`] index.
+const STEP: u16 = 64;
+
+#[derive(Clone, Eq, PartialEq, Hash, Default, Debug)]
 pub struct Line {
     string: String,
     width: u16,
+    /// Bytes offset and column of the cell covering each multiple of [`STEP`].
+    index: Vec<(usize, u16)>,
+    profile: Profile,
+    tabs: Tabs,
+    controls: Controls,
 }
 
 impl Line {
+    /// Returns a [`Line`] of `string`, with its control characters stripped and its
+    /// tabs expanded.
     pub fn new(string: String) -> Self {
-        let width = width(&string);
+        Self::with_profile(string, default())
+    }
 
-        Self { width, string }
+    /// Returns a [`Line`] measured with `profile`.
+    pub fn with_profile(string: String, profile: Profile) -> Self {
+        let mut line = Self {
+            width: 0,
+            string: String::new(),
+            index: Vec::new(),
+            profile,
+            tabs: default(),
+            controls: default(),
+        };
+        line.string = match line.sanitize(&string, 0) {
+            Cow::Borrowed(_) => string,
+            Cow::Owned(sanitized) => sanitized,
+        };
+        line.width = width(&line.string, &line.profile);
+        line.reindex(0, 0, line.string.len());
+
+        line
     }
 
     pub fn width(&self) -> u16 {
         self.width
     }
 
-    pub fn cells(&self) -> cell::Cells {
-        cell::Cells::new(&self.string)
+    /// Returns the [`Profile`] of this [`Line`].
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// Returns the [`Tabs`] of this [`Line`].
+    pub fn tabs(&self) -> &Tabs {
+        &self.tabs
+    }
+
+    /// Sets the [`Tabs`] of this [`Line`], for tabs added afterwards.
+    pub fn set_tabs(&mut self, tabs: Tabs) {
+        self.tabs = tabs;
+    }
+
+    /// Returns the [`Controls`] policy of this [`Line`].
+    pub fn controls(&self) -> Controls {
+        self.controls
+    }
+
+    /// Sets the [`Controls`] policy of this [`Line`], for text added afterwards.
+    pub fn set_controls(&mut self, controls: Controls) {
+        self.controls = controls;
+    }
+
+    pub fn cells(&self) -> cell::Cells<'_> {
+        cell::Cells::with_profile(&self.string, &self.profile)
     }
 
     /// Adds `str` to the [`Line`] and returns the actual added width.
+    ///
+    /// The added width includes the change of the last grapheme when `str` joins it,
+    /// and is zero when the joined grapheme gets narrower.
     pub fn push(&mut self, str: &str) -> u16 {
+        let before = self.width;
+        let str = &*self.sanitize(str, self.width);
+
         // Easy
         if str.is_empty() {
             return 0;
         }
         // Peasy
-        else if self.string.len() == 0 {
-            let width = width(str);
+        else if self.string.is_empty() {
+            let width = width(str, &self.profile);
 
             self.string.push_str(str);
             self.width = width;
+            self.reindex(0, 0, self.string.len());
 
             return width;
         }
@@ -58,8 +270,9 @@ impl Line {
             _ => unreachable!(),
         };
 
-        // The str without the overlapping grapheme, if any
-        let str = if !is_boundary {
+        // The str without the overlapping grapheme, if any,
+        // and where to index from
+        let (str, from) = if !is_boundary {
             // We are joining to a grapheme
             let start = match cursor.prev_boundary(&self.string, 0) {
                 Ok(Some(start)) => start,
@@ -75,55 +288,117 @@ impl Line {
             // Adjust the width for the overlapping grapheme
             // Due to the "woman scientist issue" in `unicode_width`
             // we cannot simply `width += string[at..end]`...
-            self.width -= width(&self.string[start..at]);
-            self.width += width(&self.string[start..end]);
+            self.width -= width(&self.string[start..at], &self.profile);
+            let column = self.width;
+            self.width += width(&self.string[start..end], &self.profile);
 
             // Give the new full graphemes
-            &self.string[end..]
+            (&self.string[end..], (start, column))
         } else {
-            str
+            (str, (at, self.width))
         };
 
-        // Add and return the width
-        let width = width(str);
+        // Add the width
+        let width = width(str, &self.profile);
         self.width += width;
 
-        width
+        // Index the new cells
+        let (index, column) = from;
+        let checkpoints = self.index.partition_point(|(i, _)| *i < index);
+        self.index.truncate(checkpoints);
+        self.reindex(index, column, self.string.len());
+
+        self.width.saturating_sub(before)
     }
 
-    // TODO ZWNJ on start/end
-    pub fn paint(&mut self, column: u16, str: &str) -> u16 {
-        debug_assert!(!str.contains('\n'));
+    /// Returns the cell covering `column`.
+    pub fn cell_at(&self, column: u16) -> Option<cell::Cell<'_>> {
+        let (index, c) = *self.index.get((column / STEP) as usize)?;
+
+        cell::Cells::at(&self.string, index, c, &self.profile)
+            .find(|cell| cell.column + cell.width > column)
+    }
+
+    /// Returns the cells from the one covering `column`.
+    pub fn cells_at(&self, column: u16) -> cell::Cells<'_> {
+        match self.cell_at(column) {
+            Some(cell) => cell::Cells::at(&self.string, cell.index, cell.column, &self.profile),
+            None => cell::Cells::at(&self.string, self.string.len(), self.width, &self.profile),
+        }
+    }
+
+    /// Returns the string of the cells in `columns`, when no wide cell is cut at the edges.
+    pub fn slice(&self, columns: Range<u16>) -> Option<&str> {
+        let index = |column: u16| {
+            if column == self.width {
+                return Some(self.string.len());
+            }
+
+            let cell = self.cell_at(column)?;
+            (cell.column == column).then_some(cell.index)
+        };
+
+        Some(&self.string[index(columns.start)?..index(columns.end)?])
+    }
+
+    /// Sanitizes control characters of `str` and expands its tabs (from `column`).
+    ///
+    /// Sanitizing first, so that tabs expand past the caret notations.
+    pub fn sanitize<'a>(&self, str: &'a str, column: u16) -> Cow<'a, str> {
+        match self.controls.sanitize(str) {
+            Cow::Borrowed(str) => self.tabs.expand(str, column, &self.profile),
+            Cow::Owned(sanitized) => Cow::Owned(
+                self.tabs
+                    .expand(&sanitized, column, &self.profile)
+                    .into_owned(),
+            ),
+        }
+    }
 
+    /// Indexes the cells from `index` (at `column`) to `end`.
+    fn reindex(&mut self, index: usize, column: u16, end: usize) {
+        for cell in cell::Cells::at(&self.string[..end], index, column, &self.profile) {
+            // The checkpoint possibly covered by this cell
+            let checkpoint = (cell.column + cell.width - 1) / STEP;
+
+            if checkpoint * STEP >= cell.column {
+                let checkpoint = checkpoint as usize;
+                let entry = (cell.index, cell.column);
+
+                if checkpoint < self.index.len() {
+                    self.index[checkpoint] = entry;
+                } else {
+                    debug_assert_eq!(checkpoint, self.index.len());
+                    self.index.push(entry);
+                }
+            }
+        }
+    }
+
+    /// Paints `str` at `column` and returns the painted width.
+    ///
+    /// Zero-width non-joiners (`\u{200C}`) are inserted around `str` where graphemes
+    /// would otherwise join.
+    pub fn paint(&mut self, column: u16, str: &str) -> u16 {
         // Nothing to do when `column` is outside the `Line`
         if column >= self.width {
             return 0;
         }
 
-        // Find the width of `str`
-        let (str, width) = {
-            // Crop `str` to the available width
-            let last = cell::Cells::new(str)
-                .take_while(|cell| cell.column + cell.width <= self.width - column)
-                .last();
+        // Sanitize and crop `str` to the available width
+        let str = &*self.sanitize(str, column);
+        let (str, width) = cell::crop(str, self.width - column, &self.profile);
+        let str = &*detach(str, &self.profile);
 
-            // There is nothing to paint
-            let Some(last) = last else { return 0; };
-
-            (
-                // Crop after the last cell
-                &str[..last.index + last.str.len()],
-                // Total width
-                last.column + last.width,
-            )
-        };
+        // There is nothing to paint
+        if width == 0 {
+            return 0;
+        }
 
         // Find the index of `column` in the `Line`
         let (start, wide_start) = {
             // Find the cell at `column`
-            let cell = cell::Cells::new(&self.string)
-                .skip_while(|cell| cell.column + cell.width <= column)
-                .next();
+            let cell = self.cell_at(column);
 
             // We already `column` is inside the `Line`
             debug_assert!(cell.is_some());
@@ -145,13 +420,8 @@ impl Line {
 
         // Find the index of `column + width` in the `Line`
         let (end, wide_end) = {
-            // On a wide start, we are actually starting a space before `column`
-            let width = if wide_start { width + 1 } else { width };
-
-            // Find the cell at `column + width`
-            let cell = cell::Cells::new(&self.string[start..])
-                .skip_while(|cell| cell.column + cell.width < width)
-                .next();
+            // Find the cell at the last painted column
+            let cell = self.cell_at(column + width - 1);
 
             // We already know `column + width` is inside the `Line`
             debug_assert!(cell.is_some());
@@ -160,9 +430,9 @@ impl Line {
             (
                 // We migth end on a wide cell
                 // Include it anyway and remember the wide end
-                start + cell.index + cell.str.len(),
-                if cell.column + cell.width != width {
-                    debug_assert!(cell.column + 1 == width);
+                cell.index + cell.str.len(),
+                if cell.column + cell.width != column + width {
+                    debug_assert!(cell.column + 1 == column + width);
                     debug_assert!(cell.width == 2);
                     true
                 } else {
@@ -172,23 +442,214 @@ impl Line {
         };
 
         // Replace `str` in the `Line`
-        {
-            // We include zero-width non-joiners (`\u{200C}`) around the painted columns
-            // to prevent graphemes to join before/after (this is a feature!)
-            // TODO do not include ZWNJ when already there or at the beginning/end of the line
-            let before = if wide_start { " \u{200C}" } else { "\u{200C}" };
-            let after = if wide_end { "\u{200C} " } else { "\u{200C}" };
+        let new_end = {
+            // Half wide cells become spaces
+            let before = if wide_start { " " } else { "" };
+            let after = if wide_end { " " } else { "" };
 
-            // NOTE we can do better in terms of performance (copy once) but it would be unsafe...
-            self.string.insert_str(start, before);
-            self.string
-                .replace_range(start + before.len()..end + before.len(), str);
             self.string
-                .insert_str(start + before.len() + str.len(), after);
+                .replace_range(start..end, &format!("{before}{str}{after}"));
+
+            // We include zero-width non-joiners (`\u{200C}`) around the painted columns
+            // to prevent graphemes to join before/after (this is a feature!),
+            // but only where they would actually join.
+            // The start goes first: its ZWNJ can change how the end joins
+            // (eg the parity of regional indicators)
+            let mut new_end = start + before.len() + str.len() + after.len();
+            let mut at = start + before.len();
+            for len in [0, str.len()] {
+                at += len;
+                if !breaks(&self.string[..at], &self.string[at..]) {
+                    self.string.insert(at, ZWNJ);
+                    at += ZWNJ.len_utf8();
+                    new_end += ZWNJ.len_utf8();
+                }
+            }
+
+            new_end
+        };
+
+        // Update the index
+        {
+            // Cells after the painted columns only moved
+            let moved = self.index.partition_point(|(i, _)| *i < end);
+            for (i, _) in &mut self.index[moved..] {
+                *i = *i - end + new_end;
+            }
+
+            // Cells in the painted columns changed
+            self.reindex(start, column - wide_start as u16, new_end);
         }
 
         width
     }
+
+    /// Erases `width` columns at `column` with spaces and returns the erased width.
+    ///
+    /// Wide graphemes cut at the edges are replaced by spaces.
+    pub fn erase(&mut self, column: u16, width: u16) -> u16 {
+        // Nothing to do when `column` is outside the `Line`
+        if column >= self.width {
+            return 0;
+        }
+
+        let width = width.min(self.width - column);
+        self.paint(column, &" ".repeat(width as usize))
+    }
+
+    /// Inserts `str` at `column`, shifting the following cells right, and returns the
+    /// inserted width.
+    ///
+    /// The width of the [`Line`] does not change: cells shifted past the end are dropped.
+    /// Wide graphemes cut at `column` or at the end are replaced by spaces.
+    pub fn insert(&mut self, column: u16, str: &str) -> u16 {
+        // Nothing to do when `column` is outside the `Line`
+        if column >= self.width {
+            return 0;
+        }
+
+        // Sanitize and crop `str` to the available width
+        let str = &*self.sanitize(str, column);
+        let (str, width) = cell::crop(str, self.width - column, &self.profile);
+        let str = &*detach(str, &self.profile);
+
+        // There is nothing to insert
+        if width == 0 {
+            return 0;
+        }
+
+        let total = self.width;
+        let tail = self.split_off(column);
+        self.append(str);
+        self.append(&tail);
+        self.truncate(total);
+
+        width
+    }
+
+    /// Deletes `width` columns at `column`, shifting the following cells left, and
+    /// returns the deleted width.
+    ///
+    /// The width of the [`Line`] does not change: the end is filled with spaces.
+    /// Wide graphemes cut at the edges are replaced by spaces.
+    pub fn delete(&mut self, column: u16, width: u16) -> u16 {
+        // Nothing to do when `column` is outside the `Line`
+        if column >= self.width {
+            return 0;
+        }
+
+        let width = width.min(self.width - column);
+
+        // There is nothing to delete
+        if width == 0 {
+            return 0;
+        }
+
+        let tail = self.split_off(column + width);
+        self.split_off(column);
+        self.append(&tail);
+        self.append(&" ".repeat(width as usize));
+
+        width
+    }
+
+    /// Splits the [`Line`] at `column` and returns the string after.
+    ///
+    /// A wide grapheme cut at `column` is replaced by spaces.
+    fn split_off(&mut self, column: u16) -> String {
+        let Some(cell) = self.cell_at(column) else { return String::new(); };
+
+        // We might cut a wide cell
+        if cell.column != column {
+            self.erase(column, 1);
+        }
+
+        let index = self.cell_at(column).map(|cell| cell.index);
+
+        // We already know `column` is inside the `Line`
+        debug_assert!(index.is_some());
+        let Some(index) = index else { return String::new(); };
+
+        self.width = column;
+        self.index.truncate(column.div_ceil(STEP) as usize);
+        self.string.split_off(index)
+    }
+
+    /// Pushes `str`, preventing it to join to the end of the [`Line`].
+    ///
+    /// A first grapheme of `str` that cannot start a grapheme is detached.
+    fn append(&mut self, str: &str) {
+        let str = match self.string.is_empty() {
+            true => Cow::Borrowed(str),
+            false => detach(str, &self.profile),
+        };
+
+        if !breaks(&self.string, &str) {
+            self.push(&ZWNJ.to_string());
+        }
+
+        self.push(&str);
+    }
+
+    /// Removes the zero-width non-joiners (`\u{200C}`) not preventing graphemes to join.
+    pub fn compact(&mut self) {
+        let mut string = String::with_capacity(self.string.len());
+        let mut rest = self.string.as_str();
+
+        while let Some(at) = rest.find(ZWNJ) {
+            string.push_str(&rest[..at]);
+            rest = &rest[at + ZWNJ.len_utf8()..];
+
+            // Keep it when graphemes would join without it, or when it starts the
+            // grapheme of a leading extending character or spacing mark: the width
+            // of a grapheme is the one of its first character
+            if !breaks(&string, rest)
+                || (string.is_empty() && !is_boundary(ZWNJ.encode_utf8(&mut [0; 4]), rest))
+            {
+                string.push(ZWNJ);
+            }
+        }
+
+        // Nothing to do when all zero-width non-joiners are needed
+        if string.len() + rest.len() == self.string.len() {
+            return;
+        }
+
+        string.push_str(rest);
+        *self = Self {
+            tabs: self.tabs.clone(),
+            controls: self.controls,
+            ..Self::with_profile(string, self.profile.clone())
+        };
+    }
+
+    /// Truncates the [`Line`] to `width`.
+    ///
+    /// A wide grapheme cut at the edge is replaced by a space.
+    pub fn truncate(&mut self, width: u16) {
+        // Nothing to do when `width` is outside the `Line`
+        if width >= self.width {
+            return;
+        }
+
+        // Find the cell at `width`
+        let cell = self.cell_at(width).map(|cell| (cell.index, cell.column));
+
+        // We already know `width` is inside the `Line`
+        debug_assert!(cell.is_some());
+        let Some((index, column)) = cell else { return; };
+
+        self.string.truncate(index);
+        self.index.truncate(width.div_ceil(STEP) as usize);
+
+        // We might cut a wide cell
+        if column != width {
+            debug_assert!(column + 1 == width);
+            self.string.push(' ');
+        }
+
+        self.width = width;
+    }
 }
 
 #[cfg(test)]
@@ -210,7 +671,118 @@ mod tests {
         assert_eq!(line.width, width);
     }
 
+    #[test_case("a±α", Profile::Narrow => (4, vec![1, 1, 1, 1]); "Narrow")]
+    #[test_case("a±α", Profile::Wide   => (7, vec![2, 1, 2, 2]); "Wide")]
+    fn profile(str: &str, profile: Profile) -> (u16, Vec<u16>) {
+        let mut line = Line::with_profile(str.into(), profile.clone());
+        line.push("±");
+        line.paint(0, "α");
+        assert_eq!(line.profile(), &profile);
+        assert_eq!(
+            line.index,
+            Line::with_profile(line.string.clone(), profile).index
+        );
+
+        (line.width, line.cells().map(|cell| cell.width).collect())
+    }
+
+    #[test_case("ab", 0, "\u{2764}\u{FE0F}" => (2, f!("\u{2764}\u{FE0F}")); "Emoji presentation")]
+    #[test_case("abc", 1, "🇫🇷" => (2, f!("a🇫🇷")); "Flag")]
+    #[test_case("abc", 0, "\u{231A}\u{FE0E}" => (1, f!("\u{231A}\u{FE0E}bc")); "Text presentation")]
+    fn emoji(initial: &str, column: u16, str: &str) -> (u16, String) {
+        let mut line = Line::new(initial.into());
+        let width = line.paint(column, str);
+        assert_eq!(line.width, Line::new(line.string.clone()).width);
+
+        (width, line.string)
+    }
+
+    #[test_case(Tabs::Every(8), 0  => 8 ; "Every at 0")]
+    #[test_case(Tabs::Every(8), 7  => 8 ; "Every at 7")]
+    #[test_case(Tabs::Every(8), 8  => 16; "Every at 8")]
+    #[test_case(Tabs::Every(4), 5  => 8 ; "Every 4")]
+    #[test_case(Tabs::Stops([2, 6].into()), 0 => 2; "Stops at 0")]
+    #[test_case(Tabs::Stops([2, 6].into()), 2 => 6; "Stops at 2")]
+    #[test_case(Tabs::Stops([2, 6].into()), 6 => 7; "Stops after last")]
+    #[test_case(Tabs::Every(8), u16::MAX => u16::MAX; "Every at max")]
+    #[test_case(Tabs::Every(1), u16::MAX => u16::MAX; "Every 1 at max")]
+    #[test_case(Tabs::Every(0), u16::MAX => u16::MAX; "Every 0 at max")]
+    #[test_case(Tabs::Stops([2, 6].into()), u16::MAX => u16::MAX; "Stops at max")]
+    fn tabs(tabs: Tabs, column: u16) -> u16 {
+        tabs.next(column)
+    }
+
+    #[test]
+    fn expand() {
+        let mut line = Line::new("a\tb".into());
+        assert_eq!((line.string.as_str(), line.width), ("a       b", 9));
+
+        line.set_tabs(Tabs::Every(4));
+        line.push("\tc\t");
+        assert_eq!(line.string, "a       b   c   ");
+
+        line.paint(1, "🦀\td");
+        assert_eq!(line.string, "a🦀 d   b   c   ");
+
+        line.insert(0, "\t");
+        assert_eq!(line.string, "    a🦀 d   b   ");
+        assert_eq!(line.width, 16);
+        assert_eq!(line.index, Line::new(line.string.clone()).index);
+    }
+
+    #[test_case(Controls::Strip,   "a\x1b[31mb\r\n\u{9B}c\x7f" => "a[31mbc"; "Strip")]
+    #[test_case(Controls::Caret,   "a\x1b[31mb\r\n\u{9B}c\x7f" => "a^[[31mb^M^J^[[c^?"; "Caret")]
+    #[test_case(Controls::Replace, "a\x1b[31mb\r\n\u{9B}c\x7f" => "a�[31mb���c�"; "Replace")]
+    #[test_case(Controls::Strip,   "a\u{200C}é\u{200D}" => "a\u{200C}é\u{200D}"; "Format characters")]
+    #[test_case(Controls::Caret,   "a\tb" => "a\tb"; "Tabs")]
+    fn controls(controls: Controls, str: &str) -> String {
+        controls.sanitize(str).into_owned()
+    }
+
+    #[test]
+    fn sanitize() {
+        // Control characters are stripped by default
+        let mut line = Line::new("a\x07\tb".into());
+        assert_eq!(line.controls(), Controls::Strip);
+        assert_eq!(line.string, "a       b");
+
+        line.set_controls(Controls::Caret);
+        line.push("\x1bc");
+        line.paint(0, "\r");
+        line.insert(2, "\x00");
+        assert_eq!(line.string, "^M^@      b^");
+        assert_eq!(line.width, 12);
+        assert_eq!(line.index, Line::new(line.string.clone()).index);
+
+        // Tabs expand after the caret notation
+        let mut line = Line::new(String::new());
+        line.set_controls(Controls::Caret);
+        line.push("\x1b\tx");
+        assert_eq!(line.string, "^[      x");
+        assert_eq!(line.cells().last().map(|cell| cell.column), Some(8));
+    }
+
+    #[test]
+    fn oracle() {
+        let mut table = WidthTable::new(Profile::Narrow);
+        table.insert("🦀", 1);
+        let profile = Profile::custom(table);
+
+        let mut line = Line::with_profile("a🦀b".into(), profile);
+        assert_eq!(line.width, 3);
+
+        line.paint(1, "🦀🦀");
+        assert_eq!(line.string, "a🦀🦀");
+        assert_eq!(
+            line.cells().map(|cell| cell.width).collect::<Vec<_>>(),
+            [1, 1, 1]
+        );
+    }
+
     #[test_case("abc🦀👩\u{200D}🔬def", 10; "Test 1")]
+    #[test_case("a❤\u{FE0F}b", 4; "Emoji presentation")]
+    #[test_case("#\u{20E3}b", 3; "Keycap")]
+    #[test_case("⌚\u{FE0E}b", 2; "Text presentation")]
     fn push(string: &str, width: u16) {
         for (i, _) in string.char_indices() {
             let mut line = Line::new(string[..i].to_string());
@@ -218,28 +790,152 @@ mod tests {
 
             assert_eq!(line.string, string);
             assert_eq!(line.width, width);
+            assert_eq!(line.index, Line::new(string.into()).index);
         }
     }
 
-    #[test_case("abc🦀d🦀f", 0, "!!!" => (3, f!("{ZWNJ}!!!{ZWNJ}🦀d🦀f")); "Paint at 0")]
-    #[test_case("abc🦀d🦀f", 1, "!!!" => (3, f!("a{ZWNJ}!!!{ZWNJ} d🦀f")); "Paint at 1")]
-    #[test_case("abc🦀d🦀f", 2, "!!!" => (3, f!("ab{ZWNJ}!!!{ZWNJ}d🦀f")); "Paint at 2")]
-    #[test_case("abc🦀d🦀f", 3, "!!!" => (3, f!("abc{ZWNJ}!!!{ZWNJ}🦀f")); "Paint at 3")]
-    #[test_case("abc🦀d🦀f", 4, "!!!" => (3, f!("abc {ZWNJ}!!!{ZWNJ} f")); "Paint at 4")]
-    #[test_case("abc🦀d🦀f", 5, "!!!" => (3, f!("abc🦀{ZWNJ}!!!{ZWNJ}f")); "Paint at 5")]
-    #[test_case("abc🦀d🦀f", 6, "!!!" => (3, f!("abc🦀d{ZWNJ}!!!{ZWNJ}")); "Paint at 6")]
-    #[test_case("abc🦀d🦀f", 7, "!!!" => (2, f!("abc🦀d {ZWNJ}!!{ZWNJ}")); "Paint at 7")]
-    #[test_case("abc🦀d🦀f", 8, "!!!" => (1, f!("abc🦀d🦀{ZWNJ}!{ZWNJ}")); "Paint at 8")]
+    #[test_case("abc🦀d🦀f", 0, "!!!" => (3, f!("!!!🦀d🦀f")); "Paint at 0")]
+    #[test_case("abc🦀d🦀f", 1, "!!!" => (3, f!("a!!! d🦀f")); "Paint at 1")]
+    #[test_case("abc🦀d🦀f", 2, "!!!" => (3, f!("ab!!!d🦀f")); "Paint at 2")]
+    #[test_case("abc🦀d🦀f", 3, "!!!" => (3, f!("abc!!!🦀f")); "Paint at 3")]
+    #[test_case("abc🦀d🦀f", 4, "!!!" => (3, f!("abc !!! f")); "Paint at 4")]
+    #[test_case("abc🦀d🦀f", 5, "!!!" => (3, f!("abc🦀!!!f")); "Paint at 5")]
+    #[test_case("abc🦀d🦀f", 6, "!!!" => (3, f!("abc🦀d!!!")); "Paint at 6")]
+    #[test_case("abc🦀d🦀f", 7, "!!!" => (2, f!("abc🦀d !!")); "Paint at 7")]
+    #[test_case("abc🦀d🦀f", 8, "!!!" => (1, f!("abc🦀d🦀!")); "Paint at 8")]
     #[test_case("abc🦀d🦀f", 9, "!!!" => (0, f!("abc🦀d🦀f")); "Paint at 9")]
+    #[test_case("a🇫b", 2, "🇷" => (1, f!("a🇫{ZWNJ}🇷")); "Regional indicator before")]
+    #[test_case("a🇫b", 0, "🇷" => (1, f!("🇷{ZWNJ}🇫b")); "Regional indicator after")]
+    #[test_case("🇫a🇷", 1, "🇫" => (1, f!("🇫{ZWNJ}🇫{ZWNJ}🇷")); "Regional indicator before and after")]
+    #[test_case("a👩\u{200D}bc", 3, "🔬" => (2, f!("a👩\u{200D}{ZWNJ}🔬")); "Zero width joiner")]
+    #[test_case("b❤ᄀ\u{FE0F}  ", 2, "\u{20E3}\u{200D}🦀±" => (3, f!("b❤🦀± ")); "Leading zero width joiner")]
+    #[test_case("abc", 1, "\u{0903}d" => (2, f!("a d")); "Leading spacing mark")]
+    #[test_case(f!("a{ZWNJ}!!{ZWNJ}b").as_str(), 1, "??" => (2, f!("a{ZWNJ}??b")); "Existing zero width non joiner")]
     fn paint(initial: &str, column: u16, str: &str) -> (u16, String) {
         let mut line = Line::new(initial.into());
         let width = line.width;
 
         let w = line.paint(column, str);
         assert_eq!(line.width, width);
+        assert_eq!(line.index, Line::new(line.string.clone()).index);
 
         (w, line.string)
     }
+
+    #[test_case("abc🦀d🦀f", 0, 3  => f!("   🦀d🦀f"); "Erase at 0")]
+    #[test_case("abc🦀d🦀f", 2, 3  => f!("ab   d🦀f"); "Erase at 2")]
+    #[test_case("abc🦀d🦀f", 4, 3  => f!("abc     f"); "Erase wide edges")]
+    #[test_case("abc🦀d🦀f", 4, 99 => f!("abc      "); "Erase to end")]
+    #[test_case("abc🦀d🦀f", 9, 1  => f!("abc🦀d🦀f"); "Erase outside")]
+    fn erase(initial: &str, column: u16, width: u16) -> String {
+        let mut line = Line::new(initial.into());
+        let w = line.width;
+
+        line.erase(column, width);
+        assert_eq!(line.width, w);
+        assert_eq!(line.index, Line::new(line.string.clone()).index);
+
+        line.string
+    }
+
+    #[test_case("abc🦀d🦀f", 0, "!!"  => (2, f!("!!abc🦀d ")); "Insert at 0")]
+    #[test_case("abc🦀d🦀f", 4, "!"   => (1, f!("abc ! d🦀")); "Insert in wide")]
+    #[test_case("abc🦀d🦀f", 5, "!"   => (1, f!("abc🦀!d🦀")); "Insert drops end")]
+    #[test_case("abc🦀d🦀f", 7, "!!!" => (2, f!("abc🦀d !!")); "Insert cropped")]
+    #[test_case("a🇫b", 2, "🇷"       => (1, f!("a🇫{ZWNJ}🇷")); "Insert joining")]
+    #[test_case("abc", 3, "!"        => (0, f!("abc")); "Insert outside")]
+    fn insert(initial: &str, column: u16, str: &str) -> (u16, String) {
+        let mut line = Line::new(initial.into());
+        let width = line.width;
+
+        let w = line.insert(column, str);
+        assert_eq!(line.width, width);
+        assert_eq!(line.index, Line::new(line.string.clone()).index);
+
+        (w, line.string)
+    }
+
+    #[test_case("abc🦀d🦀f", 0, 2  => (2, f!("c🦀d🦀f  ")); "Delete at 0")]
+    #[test_case("abc🦀d🦀f", 2, 2  => (2, f!("ab d🦀f  ")); "Delete in wide")]
+    #[test_case("abc🦀d🦀f", 4, 3  => (3, f!("abc  f   ")); "Delete wide edges")]
+    #[test_case("abc🦀d🦀f", 5, 99 => (4, f!("abc🦀    ")); "Delete to end")]
+    #[test_case("a🇫b🇷", 2, 1     => (1, f!("a🇫{ZWNJ}🇷 ")); "Delete joining")]
+    fn delete(initial: &str, column: u16, width: u16) -> (u16, String) {
+        let mut line = Line::new(initial.into());
+        let w = line.width;
+
+        let width = line.delete(column, width);
+        assert_eq!(line.width, w);
+        assert_eq!(line.index, Line::new(line.string.clone()).index);
+
+        (width, line.string)
+    }
+
+    #[test]
+    fn paint_repeatedly() {
+        let mut line = Line::new("a🇫🇷b".into());
+
+        for _ in 0..10 {
+            line.paint(1, "🇷");
+            line.paint(2, "🇫");
+        }
+
+        assert_eq!(line.string, f!("a🇷{ZWNJ}🇫b"));
+    }
+
+    #[test_case("abc" => "abc"; "Nothing")]
+    #[test_case(f!("{ZWNJ}a{ZWNJ}{ZWNJ}b{ZWNJ}").as_str() => "ab"; "Unneeded")]
+    #[test_case(f!("🇫{ZWNJ}{ZWNJ}🇷{ZWNJ}🇫").as_str() => f!("🇫{ZWNJ}🇷{ZWNJ}🇫"); "Needed")]
+    #[test_case(f!("{ZWNJ}\u{0903}b").as_str() => f!("{ZWNJ}\u{0903}b"); "Leading spacing mark")]
+    fn compact(string: &str) -> String {
+        let mut line = Line::new(string.into());
+        let width = line.width;
+
+        line.compact();
+        assert_eq!(line.width, width);
+        assert_eq!(line.index, Line::new(line.string.clone()).index);
+
+        line.string
+    }
+
+    #[test]
+    fn index() {
+        let string = "abc🦀d🦀f".repeat(50);
+        let mut line = Line::new(string);
+
+        // Same as a linear scan
+        for column in 0..line.width {
+            let cell = line.cells().find(|cell| cell.column + cell.width > column);
+            assert_eq!(line.cell_at(column), cell);
+        }
+        assert_eq!(line.cell_at(line.width), None);
+
+        for column in (0..line.width).step_by(7) {
+            line.paint(column, "!🦀👩\u{200D}🔬!");
+            assert_eq!(line.index, Line::new(line.string.clone()).index);
+        }
+
+        line.truncate(300);
+        line.push("🦀".repeat(100).as_str());
+        assert_eq!(line.index, Line::new(line.string.clone()).index);
+    }
+
+    #[test_case("abc🦀d", 0 => ""         ; "Truncate at 0")]
+    #[test_case("abc🦀d", 3 => "abc"      ; "Truncate at 3")]
+    #[test_case("abc🦀d", 4 => "abc "     ; "Truncate at 4")]
+    #[test_case("abc🦀d", 5 => "abc🦀"    ; "Truncate at 5")]
+    #[test_case("abc🦀d", 6 => "abc🦀d"   ; "Truncate at 6")]
+    #[test_case("abc🦀d", 7 => "abc🦀d"   ; "Truncate at 7")]
+    fn truncate(initial: &str, width: u16) -> String {
+        let mut line = Line::new(initial.into());
+        line.truncate(width);
+
+        assert_eq!(line.width, width.min(6));
+        assert_eq!(line.width, Line::new(line.string.clone()).width);
+        assert_eq!(line.index, Line::new(line.string.clone()).index);
+
+        line.string
+    }
 }
 
 pub mod cell {
@@ -259,18 +955,50 @@ pub mod cell {
         graphemes: Graphemes<'a>,
         index: usize,
         column: u16,
+        profile: Profile,
     }
 
     impl<'a> Cells<'a> {
         pub fn new(str: &'a str) -> Self {
+            Self::with_profile(str, &default())
+        }
+
+        /// Returns the cells of `str` measured with `profile`.
+        pub fn with_profile(str: &'a str, profile: &Profile) -> Self {
+            Self::at(str, 0, 0, profile)
+        }
+
+        /// Returns the cells of `str` from `index`, starting at `column`.
+        ///
+        /// `index` must be a grapheme boundary.
+        pub fn at(str: &'a str, index: usize, column: u16, profile: &Profile) -> Self {
             Self {
-                graphemes: UnicodeSegmentation::graphemes(str, true),
-                index: 0,
-                column: 0,
+                graphemes: UnicodeSegmentation::graphemes(&str[index..], true),
+                index,
+                column,
+                profile: profile.clone(),
             }
         }
     }
 
+    /// Crops `str` to the cells fitting in `width`, measured with `profile`.
+    ///
+    /// Returns the cropped `str` and its width.
+    pub fn crop<'a>(str: &'a str, width: u16, profile: &Profile) -> (&'a str, u16) {
+        let last = Cells::with_profile(str, profile)
+            .take_while(|cell| cell.column + cell.width <= width)
+            .last();
+
+        match last {
+            // Crop after the last cell
+            Some(last) => (
+                &str[..last.index + last.str.len()],
+                last.column + last.width,
+            ),
+            None => ("", 0),
+        }
+    }
+
     impl<'a> Iterator for Cells<'a> {
         type Item = Cell<'a>;
 
@@ -278,10 +1006,7 @@ pub mod cell {
             let str = self.graphemes.next()?;
             let index = self.index;
             let column = self.column;
-            let width = unicode_width::UnicodeWidthStr::width(str) as u16;
-
-            // `unicode_width` has trouble with some clusters (e.g. woman scientist emoji)
-            let width = width.min(2);
+            let width = grapheme_width(str, &self.profile) as u16;
 
             self.index += str.len();
             self.column += width;
@@ -0,0 +1,491 @@
+use super::*;
+use std::ops::Range;
+
+/// Escape.
+const ESC: char = '\x1b';
+/// Bell.
+const BEL: char = '\x07';
+
+/// Parses text containing ANSI escape sequences into [`Row`]s.
+///
+/// The [`Parser`] keeps its style and hyperlink between calls, so that styles
+/// spanning multiple lines (e.g. in `git diff --color`) are preserved.
+#[derive(Clone, Eq, PartialEq, Default, Debug)]
+pub struct Parser {
+    /// The style on reset.
+    base: Style,
+    /// The current style.
+    style: Style,
+    /// The current underline color, kept when not underlined.
+    underline: Color,
+    /// The current hyperlink.
+    link: Option<Link>,
+}
+
+impl Parser {
+    /// Returns a [`Parser`] starting and resetting to `style`.
+    pub fn new(style: Style) -> Self {
+        Self {
+            base: style,
+            style,
+            underline: style
+                .underline
+                .map(|underline| underline.color)
+                .unwrap_or_default(),
+            link: None,
+        }
+    }
+
+    /// Returns the current style.
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    /// Returns the current hyperlink.
+    pub fn link(&self) -> Option<&Link> {
+        self.link.as_ref()
+    }
+
+    /// Parses `str` into a new [`Row`].
+    ///
+    /// See [`Parser::push`].
+    pub fn row(&mut self, str: &str) -> (Row, Vec<Range<usize>>) {
+        let mut row = Row::default();
+        let unsupported = self.push(&mut row, str);
+
+        (row, unsupported)
+    }
+
+    /// Parses `str` and pushes it into `row`.
+    ///
+    /// Escape sequences are stripped from the text. Select Graphic Rendition
+    /// (SGR) and hyperlink (OSC 8) sequences are applied, and the bytes ranges
+    /// of other sequences in `str` are returned.
+    pub fn push(&mut self, row: &mut Row, str: &str) -> Vec<Range<usize>> {
+        let mut unsupported = Vec::new();
+        let mut rest = 0;
+
+        while let Some(start) = str[rest..].find(ESC).map(|i| rest + i) {
+            self.text(row, &str[rest..start]);
+
+            let end = start + len(&str[start..]);
+            if !self.sequence(&str[start..end]) {
+                unsupported.push(start..end);
+            }
+
+            rest = end;
+        }
+
+        self.text(row, &str[rest..]);
+
+        unsupported
+    }
+
+    fn text(&self, row: &mut Row, str: &str) {
+        if str.is_empty() {
+            return;
+        }
+
+        match &self.link {
+            Some(link) => row.push_link(str, self.style, link.clone()),
+            None => row.push(str, self.style),
+        }
+    }
+
+    /// Applies `sequence`, returning whether it is supported.
+    fn sequence(&mut self, sequence: &str) -> bool {
+        if let Some(params) = sequence
+            .strip_prefix("\x1b[")
+            .and_then(|sequence| sequence.strip_suffix('m'))
+        {
+            self.sgr(params)
+        } else if let Some(osc) = sequence
+            .strip_prefix("\x1b]8;")
+            .and_then(|osc| osc.strip_suffix(BEL).or_else(|| osc.strip_suffix("\x1b\\")))
+        {
+            self.hyperlink(osc)
+        } else {
+            false
+        }
+    }
+
+    /// Applies Select Graphic Rendition `params`, returning whether they are all supported.
+    fn sgr(&mut self, params: &str) -> bool {
+        // Private and intermediate bytes are not SGR
+        if !params
+            .bytes()
+            .all(|byte| byte.is_ascii_digit() || b":;".contains(&byte))
+        {
+            return false;
+        }
+
+        let mut supported = true;
+        let mut params = params.split(';');
+
+        while let Some(param) = params.next() {
+            let mut subs = param.split(':');
+            let code = match subs.next() {
+                Some("") | None => 0,
+                Some(code) => match code.parse::<u16>() {
+                    Ok(code) => code,
+                    Err(_) => {
+                        supported = false;
+                        continue;
+                    }
+                },
+            };
+
+            let attributes = &mut self.style.attributes;
+            match code {
+                0 => self.reset(),
+                1 => attributes.insert(Attributes::BOLD),
+                2 => attributes.insert(Attributes::DIM),
+                3 => attributes.insert(Attributes::ITALIC),
+                4 => match subs.next() {
+                    None | Some("1") => self.underline(Some(UnderlineStyle::Single)),
+                    Some("0") => self.underline(None),
+                    Some("2") => self.underline(Some(UnderlineStyle::Double)),
+                    Some("3") => self.underline(Some(UnderlineStyle::Curl)),
+                    Some("4") => self.underline(Some(UnderlineStyle::Dot)),
+                    Some("5") => self.underline(Some(UnderlineStyle::Dash)),
+                    Some(_) => supported = false,
+                },
+                5 | 6 => attributes.insert(Attributes::BLINK),
+                7 => attributes.insert(Attributes::REVERSE),
+                8 => attributes.insert(Attributes::HIDDEN),
+                9 => attributes.insert(Attributes::STRIKE),
+                21 => self.underline(Some(UnderlineStyle::Double)),
+                22 => {
+                    attributes.remove(Attributes::BOLD);
+                    attributes.remove(Attributes::DIM);
+                }
+                23 => attributes.remove(Attributes::ITALIC),
+                24 => self.underline(None),
+                25 => attributes.remove(Attributes::BLINK),
+                27 => attributes.remove(Attributes::REVERSE),
+                28 => attributes.remove(Attributes::HIDDEN),
+                29 => attributes.remove(Attributes::STRIKE),
+                30..=37 => self.style.foreground = Color::Ansi(Ansi::ALL[code as usize - 30]),
+                39 => self.style.foreground = Color::Default,
+                40..=47 => self.style.background = Color::Ansi(Ansi::ALL[code as usize - 40]),
+                49 => self.style.background = Color::Default,
+                53 => attributes.insert(Attributes::OVERLINE),
+                55 => attributes.remove(Attributes::OVERLINE),
+                59 => self.underline_color(Color::Default),
+                90..=97 => self.style.foreground = Color::Ansi(Ansi::ALL[code as usize - 82]),
+                100..=107 => self.style.background = Color::Ansi(Ansi::ALL[code as usize - 92]),
+                38 | 48 | 58 => {
+                    // Either `38:5:i` or `38;5;i` (same for `2:r:g:b`)
+                    let subs = if param.contains(':') {
+                        subs.collect::<Vec<_>>()
+                    } else {
+                        let len = match params.clone().next() {
+                            Some("5") => 2,
+                            Some("2") => 4,
+                            _ => 0,
+                        };
+                        params.by_ref().take(len).collect()
+                    };
+
+                    match (code, extended(&subs)) {
+                        (38, Some(color)) => self.style.foreground = color,
+                        (48, Some(color)) => self.style.background = color,
+                        (58, Some(color)) => self.underline_color(color),
+                        _ => supported = false,
+                    }
+                }
+                _ => supported = false,
+            }
+        }
+
+        supported
+    }
+
+    /// Applies hyperlink `osc` (`params;url`), returning whether it is supported.
+    fn hyperlink(&mut self, osc: &str) -> bool {
+        let Some((params, url)) = osc.split_once(';') else {
+            return false;
+        };
+
+        self.link = if url.is_empty() {
+            None
+        } else {
+            let id = params
+                .split(':')
+                .find_map(|param| param.strip_prefix("id="));
+
+            Some(match id {
+                Some(id) => Link::with_id(url, id),
+                None => Link::new(url),
+            })
+        };
+
+        true
+    }
+
+    fn reset(&mut self) {
+        *self = Self {
+            link: self.link.take(),
+            ..Self::new(self.base)
+        };
+    }
+
+    fn underline(&mut self, style: Option<UnderlineStyle>) {
+        self.style.underline = style.map(|style| Underline {
+            style,
+            color: self.underline,
+        });
+    }
+
+    fn underline_color(&mut self, color: Color) {
+        self.underline = color;
+
+        if let Some(underline) = &mut self.style.underline {
+            underline.color = color;
+        }
+    }
+}
+
+/// Parses the sub-parameters of an extended color (`5:i`, `2:r:g:b` or `2:id:r:g:b`).
+fn extended(subs: &[&str]) -> Option<Color> {
+    let int = |str: &str| str.parse::<u8>().ok();
+
+    match *subs {
+        ["5", index] => Some(Color::Indexed(int(index)?)),
+        ["2", r, g, b] | ["2", _, r, g, b] => Some(Color::Rgb {
+            r: int(r)?,
+            g: int(g)?,
+            b: int(b)?,
+        }),
+        _ => None,
+    }
+}
+
+/// Returns the length of the escape sequence at the start of `str`.
+fn len(str: &str) -> usize {
+    debug_assert!(str.starts_with(ESC));
+
+    let bytes = str.as_bytes();
+    let count = |from: usize, f: fn(u8) -> bool| {
+        from + bytes[from..].iter().take_while(|byte| f(**byte)).count()
+    };
+
+    match bytes.get(1) {
+        // Control Sequence Introducer: parameters, intermediates, final byte
+        Some(b'[') => {
+            let end = count(2, |byte| (0x20..=0x3F).contains(&byte));
+
+            match bytes.get(end) {
+                Some(0x40..=0x7E) => end + 1,
+                _ => end,
+            }
+        }
+        // Strings: until Bell or String Terminator
+        Some(b']' | b'P' | b'X' | b'^' | b'_') => {
+            let end = str[2..]
+                .find([BEL, ESC])
+                .map(|i| 2 + i)
+                .unwrap_or(str.len());
+
+            match bytes.get(end) {
+                Some(0x07) => end + 1,
+                Some(0x1b) if bytes.get(end + 1) == Some(&b'\\') => end + 2,
+                _ => end,
+            }
+        }
+        // Other escapes: intermediates, final byte
+        Some(0x20..=0x7E) => {
+            let end = count(1, |byte| (0x20..=0x2F).contains(&byte));
+
+            match bytes.get(end) {
+                Some(0x30..=0x7E) => end + 1,
+                _ => end,
+            }
+        }
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn cells(row: &Row) -> Vec<(&str, Style, Option<&Link>)> {
+        row.cells()
+            .map(|cell| (cell.str, cell.style, cell.link))
+            .collect()
+    }
+
+    fn fg(foreground: Color) -> Style {
+        Style {
+            foreground,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn colors() {
+        let (row, unsupported) = Parser::default()
+            .row("a\x1b[31mb\x1b[1;92mc\x1b[38;5;42md\x1b[38:2::1:2:3me\x1b[48;2;4;5;6mf\x1b[mg");
+
+        assert_eq!(unsupported, []);
+        assert_eq!(
+            cells(&row),
+            [
+                ("a", default(), None),
+                ("b", fg(Color::Ansi(Ansi::Red)), None),
+                (
+                    "c",
+                    Style {
+                        attributes: Attributes::BOLD,
+                        ..fg(Color::Ansi(Ansi::BrightGreen))
+                    },
+                    None
+                ),
+                (
+                    "d",
+                    Style {
+                        attributes: Attributes::BOLD,
+                        ..fg(Color::Indexed(42))
+                    },
+                    None
+                ),
+                (
+                    "e",
+                    Style {
+                        attributes: Attributes::BOLD,
+                        ..fg(Color::Rgb { r: 1, g: 2, b: 3 })
+                    },
+                    None
+                ),
+                (
+                    "f",
+                    Style {
+                        attributes: Attributes::BOLD,
+                        background: Color::Rgb { r: 4, g: 5, b: 6 },
+                        ..fg(Color::Rgb { r: 1, g: 2, b: 3 })
+                    },
+                    None
+                ),
+                ("g", default(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn underline() {
+        let (row, unsupported) =
+            Parser::default().row("\x1b[58:5:1;4:3ma\x1b[4:0mb\x1b[21mc\x1b[24;59;4md");
+
+        assert_eq!(unsupported, []);
+        assert_eq!(
+            cells(&row)
+                .into_iter()
+                .map(|(str, style, _)| (str, style.underline))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "a",
+                    Some(Underline {
+                        style: UnderlineStyle::Curl,
+                        color: Color::Indexed(1),
+                    })
+                ),
+                ("b", None),
+                (
+                    "c",
+                    Some(Underline {
+                        style: UnderlineStyle::Double,
+                        color: Color::Indexed(1),
+                    })
+                ),
+                ("d", Some(default())),
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        let str = "a\x1b[2Jb\x1b[?25lc\x1b[31;99md\x1b]0;title\x07e\x1b7f\x1b";
+        let (row, unsupported) = Parser::default().row(str);
+
+        assert_eq!(
+            unsupported
+                .into_iter()
+                .map(|range| &str[range])
+                .collect::<Vec<_>>(),
+            [
+                "\x1b[2J",
+                "\x1b[?25l",
+                "\x1b[31;99m",
+                "\x1b]0;title\x07",
+                "\x1b7",
+                "\x1b"
+            ]
+        );
+        assert_eq!(
+            cells(&row),
+            [
+                ("a", default(), None),
+                ("b", default(), None),
+                ("c", default(), None),
+                ("d", fg(Color::Ansi(Ansi::Red)), None),
+                ("e", fg(Color::Ansi(Ansi::Red)), None),
+                ("f", fg(Color::Ansi(Ansi::Red)), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn hyperlink() {
+        let link = Link::with_id("https://a.com", "a");
+        let mut parser = Parser::default();
+        let (row, unsupported) =
+            parser.row("\x1b]8;id=a;https://a.com\x1b\\a\x1b[0mb\x1b]8;;\x07c");
+
+        assert_eq!(unsupported, []);
+        assert_eq!(
+            cells(&row),
+            [
+                ("a", default(), Some(&link)),
+                ("b", default(), Some(&link)),
+                ("c", default(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn state() {
+        let mut parser = Parser::default();
+        let mut row = Row::default();
+
+        assert_eq!(parser.push(&mut row, "\x1b[31ma"), []);
+        assert_eq!(parser.push(&mut row, "b"), []);
+        assert_eq!(parser.style(), fg(Color::Ansi(Ansi::Red)));
+        assert_eq!(
+            cells(&row),
+            [
+                ("a", fg(Color::Ansi(Ansi::Red)), None),
+                ("b", fg(Color::Ansi(Ansi::Red)), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_grapheme() {
+        let mut parser = Parser::default();
+        let mut row = Row::default();
+
+        // The escape sequence splits the wide heart grapheme
+        assert_eq!(parser.push(&mut row, "❤\x1b[31m\u{FE0F}b"), []);
+        assert_eq!(row.width(), 3);
+        assert_eq!(
+            cells(&row),
+            [
+                ("❤\u{FE0F}", default(), None),
+                ("b", fg(Color::Ansi(Ansi::Red)), None),
+            ]
+        );
+    }
+}
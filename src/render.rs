@@ -0,0 +1,682 @@
+use super::*;
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::ops::Range;
+
+/// Control Sequence Introducer.
+const CSI: &str = "\x1b[";
+/// Operating System Command.
+const OSC: &str = "\x1b]";
+/// String Terminator.
+const ST: &str = "\x1b\\";
+
+/// Renders [`Canvas`]es to terminals with ANSI escape sequences.
+///
+/// The [`Renderer`] remembers the cursor position and style of the terminal
+/// to avoid emitting redundant sequences.
+///
+/// Colors are downsampled to the [`Depth`] of the terminal.
+#[derive(Clone, Eq, PartialEq, Default, Debug)]
+pub struct Renderer {
+    /// The color depth of the terminal.
+    depth: Depth,
+    /// The cursor position (line, column), if known.
+    cursor: Option<(usize, u16)>,
+    /// The current style, if known.
+    style: Option<Style>,
+    /// The currently open hyperlink.
+    link: Option<Link>,
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        default()
+    }
+
+    /// Returns a [`Renderer`] for a terminal with `depth`.
+    pub fn with_depth(depth: Depth) -> Self {
+        Self { depth, ..default() }
+    }
+
+    /// Renders the whole `canvas` into `w`.
+    pub fn render(&mut self, w: &mut impl Write, canvas: &Canvas) -> io::Result<()> {
+        for (line, row) in canvas.rows().iter().enumerate() {
+            self.row(w, line, row)?;
+        }
+
+        self.reset(w)
+    }
+
+    /// Renders the differences between `prev` and `next` into `w`.
+    ///
+    /// Only the cells of `next` that are not already displayed by `prev` are
+    /// written. When dimensions differ, the screen is cleared and `next` is
+    /// fully rendered. Scrolled lines are scrolled in the terminal.
+    pub fn diff(&mut self, w: &mut impl Write, prev: &Canvas, next: &Canvas) -> io::Result<()> {
+        if prev.width() != next.width() || prev.height() != next.height() {
+            write!(w, "{CSI}2J")?;
+            self.cursor = None;
+
+            return self.render(w, next);
+        }
+
+        let mut changed = false;
+        let mut rows = prev.rows().iter().map(Some).collect::<Vec<_>>();
+
+        // Scroll when it keeps more rows in place
+        if let Some((lines, delta)) = scroll(prev.rows(), next.rows()) {
+            self.scroll(w, lines.clone(), delta)?;
+            changed = true;
+
+            let region = &prev.rows()[lines.clone()];
+            for (i, row) in rows[lines].iter_mut().enumerate() {
+                *row = usize::try_from(i as isize + delta)
+                    .ok()
+                    .and_then(|i| region.get(i));
+            }
+        }
+
+        for (line, (prev, next)) in rows.into_iter().zip(next.rows()).enumerate() {
+            match prev {
+                // Unchanged rows are skipped wholesale
+                Some(prev) if prev == next => {}
+                Some(prev) => changed |= self.row_diff(w, line, prev, next)?,
+                // Rows exposed by scrolling are blank
+                None => {
+                    self.row(w, line, next)?;
+                    changed = true;
+                }
+            }
+        }
+
+        if changed {
+            self.reset(w)?;
+        }
+
+        Ok(())
+    }
+
+    /// Renders the differences between `prev` and `next` at `line` into `w`.
+    ///
+    /// Returns whether something was written.
+    pub fn row_diff(
+        &mut self,
+        w: &mut impl Write,
+        line: usize,
+        prev: &Row,
+        next: &Row,
+    ) -> io::Result<bool> {
+        let mut changed = false;
+
+        for (_, start, span) in next.spans().scan() {
+            let columns = start..start + span.width;
+
+            // Skip unchanged stretches of the same style at once
+            if next.displays_same(prev, columns.clone()) {
+                continue;
+            }
+
+            let cells = next.cells_at(start);
+            let mut prev = prev.cells_at(start).peekable();
+
+            // Cells starting before the span were compared with the previous span
+            for cell in cells
+                .skip_while(|cell| cell.column < columns.start)
+                .take_while(|cell| cell.column < columns.end)
+            {
+                // Skip previous cells starting before this cell
+                while prev.next_if(|prev| prev.column < cell.column).is_some() {}
+
+                // A cell is up to date when the same grapheme, with the same width and
+                // style, starts at the same column. A wide cell partially overwritten in
+                // `next` does not start at the same column or has a different width, and
+                // is repainted.
+                let displayed = prev.peek() == Some(&cell);
+
+                if !displayed {
+                    self.cell(w, line, cell)?;
+                    changed = true;
+                }
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// Scrolls `lines` up by `delta` (down when negative) into `w`.
+    ///
+    /// Uses a scrolling region (`DECSTBM`), then `SU` or `SD`.
+    pub fn scroll(
+        &mut self,
+        w: &mut impl Write,
+        lines: Range<usize>,
+        delta: isize,
+    ) -> io::Result<()> {
+        // Nothing to do when not scrolling
+        if delta == 0 || lines.is_empty() {
+            return Ok(());
+        }
+
+        write!(w, "{CSI}{};{}r", lines.start + 1, lines.end)?;
+        if delta > 0 {
+            write!(w, "{CSI}{delta}S")?;
+        } else {
+            write!(w, "{CSI}{}T", -delta)?;
+        }
+        write!(w, "{CSI}r")?;
+
+        // Setting the scrolling region moves the cursor home
+        self.cursor = None;
+
+        Ok(())
+    }
+
+    /// Renders `row` at `line` into `w`.
+    pub fn row(&mut self, w: &mut impl Write, line: usize, row: &Row) -> io::Result<()> {
+        for cell in row.cells() {
+            self.cell(w, line, cell)?;
+        }
+
+        Ok(())
+    }
+
+    /// Renders `cell` at `line` into `w`.
+    pub fn cell(&mut self, w: &mut impl Write, line: usize, cell: row::Cell) -> io::Result<()> {
+        self.move_to(w, line, cell.column)?;
+        self.style(w, cell.style)?;
+        self.link(w, cell.link)?;
+        w.write_all(cell.str.as_bytes())?;
+        self.cursor = Some((line, cell.column + cell.width));
+
+        Ok(())
+    }
+
+    /// Moves the cursor to `line` and `column`, if needed.
+    pub fn move_to(&mut self, w: &mut impl Write, line: usize, column: u16) -> io::Result<()> {
+        match self.cursor {
+            // Already there
+            Some(cursor) if cursor == (line, column) => return Ok(()),
+            // Moving forward on the same line is shorter
+            Some((l, c)) if l == line && c < column => write!(w, "{CSI}{}C", column - c)?,
+            _ => write!(w, "{CSI}{};{}H", line + 1, column + 1)?,
+        }
+
+        self.cursor = Some((line, column));
+
+        Ok(())
+    }
+
+    /// Applies `style`, if needed.
+    pub fn style(&mut self, w: &mut impl Write, style: Style) -> io::Result<()> {
+        let style = style.to_depth(self.depth);
+
+        if self.style != Some(style) {
+            sgr(w, style)?;
+            self.style = Some(style);
+        }
+
+        Ok(())
+    }
+
+    /// Opens `link`, or closes the current hyperlink, if needed.
+    pub fn link(&mut self, w: &mut impl Write, link: Option<&Link>) -> io::Result<()> {
+        if self.link.as_ref() != link {
+            // Opening a hyperlink closes the current one
+            // URLs and ids cannot contain controls, nor ids the parameter separators
+            match link {
+                Some(Link { url, id: Some(id) }) => {
+                    let (url, id) = (escape(url, b""), escape(id, b":;"));
+                    write!(w, "{OSC}8;id={id};{url}{ST}")?;
+                }
+                Some(Link { url, id: None }) => write!(w, "{OSC}8;;{}{ST}", escape(url, b""))?,
+                None => write!(w, "{OSC}8;;{ST}")?,
+            }
+
+            self.link = link.cloned();
+        }
+
+        Ok(())
+    }
+
+    /// Closes the current hyperlink and resets the terminal style.
+    pub fn reset(&mut self, w: &mut impl Write) -> io::Result<()> {
+        self.link(w, None)?;
+        write!(w, "{CSI}0m")?;
+        self.style = None;
+
+        Ok(())
+    }
+}
+
+/// Percent-encodes the bytes of `str` outside `0x20..=0x7E`, and the `reserved` ones.
+fn escape<'a>(str: &'a str, reserved: &[u8]) -> Cow<'a, str> {
+    let escaped = |byte: &u8| !(0x20..=0x7E).contains(byte) || reserved.contains(byte);
+
+    // Nothing to do without bytes to escape
+    if !str.bytes().any(|byte| escaped(&byte)) {
+        return Cow::Borrowed(str);
+    }
+
+    let mut string = String::with_capacity(str.len());
+
+    for byte in str.bytes() {
+        if escaped(&byte) {
+            string.push_str(&format!("%{byte:02X}"));
+        } else {
+            string.push(byte as char);
+        }
+    }
+
+    Cow::Owned(string)
+}
+
+/// Writes the Select Graphic Rendition sequence for `style` into `w`.
+///
+/// The sequence starts with a reset, so that `style` does not depend on the
+/// previous state of the terminal.
+pub fn sgr(w: &mut impl Write, style: Style) -> io::Result<()> {
+    let Style {
+        foreground,
+        background,
+        attributes,
+        underline,
+    } = style;
+
+    write!(w, "{CSI}0")?;
+
+    for (attribute, code) in [
+        (Attributes::BOLD, 1),
+        (Attributes::DIM, 2),
+        (Attributes::ITALIC, 3),
+        (Attributes::BLINK, 5),
+        (Attributes::REVERSE, 7),
+        (Attributes::HIDDEN, 8),
+        (Attributes::STRIKE, 9),
+        (Attributes::OVERLINE, 53),
+    ] {
+        if attributes.contains(attribute) {
+            write!(w, ";{code}")?;
+        }
+    }
+    if let Some(Underline { style, color }) = underline {
+        let style = match style {
+            UnderlineStyle::Single => 1,
+            UnderlineStyle::Double => 2,
+            UnderlineStyle::Curl => 3,
+            UnderlineStyle::Dot => 4,
+            UnderlineStyle::Dash => 5,
+        };
+
+        write!(w, ";4:{style}")?;
+
+        match color {
+            // Already reset
+            Color::Default => {}
+            Color::Ansi(ansi) => write!(w, ";58:5:{}", ansi.index())?,
+            Color::Indexed(index) => write!(w, ";58:5:{index}")?,
+            Color::Rgb { r, g, b } => write!(w, ";58:2::{r}:{g}:{b}")?,
+        }
+    }
+
+    match foreground {
+        // Already reset
+        Color::Default => {}
+        Color::Ansi(ansi) if ansi.index() < 8 => write!(w, ";{}", 30 + ansi.index())?,
+        Color::Ansi(ansi) => write!(w, ";{}", 90 + ansi.index() - 8)?,
+        Color::Indexed(index) => write!(w, ";38;5;{index}")?,
+        Color::Rgb { r, g, b } => write!(w, ";38;2;{r};{g};{b}")?,
+    }
+
+    match background {
+        // Already reset
+        Color::Default => {}
+        Color::Ansi(ansi) if ansi.index() < 8 => write!(w, ";{}", 40 + ansi.index())?,
+        Color::Ansi(ansi) => write!(w, ";{}", 100 + ansi.index() - 8)?,
+        Color::Indexed(index) => write!(w, ";48;5;{index}")?,
+        Color::Rgb { r, g, b } => write!(w, ";48;2;{r};{g};{b}")?,
+    }
+
+    write!(w, "m")
+}
+
+/// The most scrolls up and down tried by [`scroll()`].
+const SCROLLS: usize = 4;
+
+/// Finds the scroll of lines from `prev` to `next` keeping the most rows in place.
+///
+/// Only tries the scrolls moving the first changed row of `prev` or `next` in place.
+///
+/// Returns the scrolled lines and how far they scroll up (down when negative).
+fn scroll(prev: &[Row], next: &[Row]) -> Option<(Range<usize>, isize)> {
+    let changed = |(prev, next): (&Row, &Row)| prev != next;
+    let top = prev.iter().zip(next).position(changed)?;
+    let bottom = prev.len() - prev.iter().zip(next).rev().position(changed)?;
+    let (prev, next) = (&prev[top..bottom], &next[top..bottom]);
+
+    // Hash each row once, to compare rows cheaply
+    let hash = |row: &Row| {
+        let mut hasher = DefaultHasher::new();
+        row.hash(&mut hasher);
+        hasher.finish()
+    };
+    let prev_hashes = prev.iter().map(hash).collect::<Vec<_>>();
+    let next_hashes = next.iter().map(hash).collect::<Vec<_>>();
+
+    // Rows of `next` displayed by `prev` scrolled up by `delta`
+    let kept = |delta: isize| {
+        next.iter()
+            .enumerate()
+            .filter(|(i, row)| {
+                usize::try_from(*i as isize + delta)
+                    .is_ok_and(|j| prev_hashes.get(j) == Some(&next_hashes[*i]) && prev[j] == **row)
+            })
+            .count()
+    };
+    let unscrolled = kept(0);
+
+    // Where the first row of `next` was, and where the first row of `prev` went
+    fn positions(hashes: &[u64], hash: u64) -> impl Iterator<Item = isize> + '_ {
+        (1..hashes.len())
+            .filter(move |i| hashes[*i] == hash)
+            .take(SCROLLS)
+            .map(|i| i as isize)
+    }
+    let up = positions(&prev_hashes, next_hashes[0]);
+    let down = positions(&next_hashes, prev_hashes[0]).map(|delta| -delta);
+
+    up.chain(down)
+        .map(|delta| (kept(delta), delta))
+        .filter(|(kept, _)| *kept > unscrolled)
+        // Prefer shorter scrolls
+        .max_by_key(|(kept, delta)| (*kept, std::cmp::Reverse(delta.abs())))
+        .map(|(_, delta)| (top..bottom, delta))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const RED: Color = Color::Rgb { r: 255, g: 0, b: 0 };
+    const GREEN: Color = Color::Rgb { r: 0, g: 255, b: 0 };
+
+    fn sgr(style: Style) -> String {
+        let mut w = Vec::new();
+        super::sgr(&mut w, style).unwrap();
+        String::from_utf8(w).unwrap()
+    }
+
+    #[test]
+    fn style() {
+        assert_eq!(sgr(default()), "\x1b[0m");
+        assert_eq!(
+            sgr(Style {
+                foreground: RED,
+                background: GREEN,
+                attributes: Attributes::BOLD | Attributes::ITALIC | Attributes::STRIKE,
+                underline: Some(Underline {
+                    style: UnderlineStyle::Curl,
+                    color: RED,
+                }),
+            }),
+            "\x1b[0;1;3;9;4:3;58:2::255:0:0;38;2;255;0;0;48;2;0;255;0m"
+        );
+        assert_eq!(
+            sgr(Style {
+                attributes: Attributes::DIM
+                    | Attributes::BLINK
+                    | Attributes::REVERSE
+                    | Attributes::HIDDEN
+                    | Attributes::OVERLINE,
+                ..default()
+            }),
+            "\x1b[0;2;5;7;8;53m"
+        );
+        assert_eq!(
+            sgr(Style {
+                foreground: Color::Ansi(Ansi::Red),
+                background: Color::Ansi(Ansi::BrightBlue),
+                underline: Some(Underline {
+                    style: UnderlineStyle::Single,
+                    color: Color::Indexed(42),
+                }),
+                ..default()
+            }),
+            "\x1b[0;4:1;58:5:42;31;104m"
+        );
+        assert_eq!(
+            sgr(Style {
+                foreground: Color::Indexed(42),
+                background: Color::Ansi(Ansi::Black),
+                underline: Some(default()),
+                ..default()
+            }),
+            "\x1b[0;4:1;38;5;42;40m"
+        );
+    }
+
+    #[test]
+    fn render() {
+        let red = Style {
+            foreground: RED,
+            ..default()
+        };
+        let green = Style {
+            foreground: GREEN,
+            ..default()
+        };
+
+        let mut row = Row::new("ab".into(), red);
+        row.push("🦀", green);
+        let canvas = Canvas::new(vec![row, Row::new("cdef".into(), red)]);
+
+        let mut w = Vec::new();
+        Renderer::new().render(&mut w, &canvas).unwrap();
+
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            [
+                "\x1b[1;1H",
+                &sgr(red),
+                "ab",
+                &sgr(green),
+                "🦀",
+                "\x1b[2;1H",
+                &sgr(red),
+                "cdef",
+                "\x1b[0m",
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn link() {
+        let mut row = Row::new("a".into(), default());
+        row.push_link("bc", default(), Link::new("https://a.com"));
+        row.push_link("d", default(), Link::with_id("https://d.com", "d"));
+        row.push("e", default());
+        let canvas = Canvas::new(vec![row]);
+
+        let mut w = Vec::new();
+        Renderer::new().render(&mut w, &canvas).unwrap();
+
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            [
+                "\x1b[1;1H\x1b[0ma",
+                "\x1b]8;;https://a.com\x1b\\bc",
+                "\x1b]8;id=d;https://d.com\x1b\\d",
+                "\x1b]8;;\x1b\\e",
+                "\x1b[0m",
+            ]
+            .concat()
+        );
+
+        let mut w = Vec::new();
+        let link = Link::with_id("https://a.com/é\x1b\\", "a:b;c\x07");
+        Renderer::new().link(&mut w, Some(&link)).unwrap();
+
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "\x1b]8;id=a%3Ab%3Bc%07;https://a.com/%C3%A9%1B\\\x1b\\"
+        );
+    }
+
+    #[test]
+    fn depth() {
+        let style = Style {
+            foreground: RED,
+            background: GREEN,
+            attributes: Attributes::BOLD,
+            ..default()
+        };
+        let canvas = Canvas::new(vec![Row::new("a".into(), style)]);
+        let render = |depth| {
+            let mut w = Vec::new();
+            Renderer::with_depth(depth).render(&mut w, &canvas).unwrap();
+            String::from_utf8(w).unwrap()
+        };
+
+        assert_eq!(
+            render(Depth::Indexed),
+            "\x1b[1;1H\x1b[0;1;38;5;196;48;5;46ma\x1b[0m"
+        );
+        assert_eq!(render(Depth::Ansi), "\x1b[1;1H\x1b[0;1;91;102ma\x1b[0m");
+        assert_eq!(render(Depth::Monochrome), "\x1b[1;1H\x1b[0;1ma\x1b[0m");
+    }
+
+    #[test]
+    fn diff() {
+        let red = Style {
+            foreground: RED,
+            ..default()
+        };
+        let green = Style {
+            foreground: GREEN,
+            ..default()
+        };
+
+        let prev = Canvas::new(vec![
+            Row::new("abcdef".into(), red),
+            Row::new("a🦀def".into(), red),
+        ]);
+        let render = |next: &Canvas| {
+            let mut w = Vec::new();
+            Renderer::new().diff(&mut w, &prev, next).unwrap();
+            String::from_utf8(w).unwrap()
+        };
+
+        // Nothing changed
+        assert_eq!(render(&prev), "");
+
+        // Changed cells only
+        let mut next = prev.clone();
+        next.paint(0, 1, "X", red);
+        next.paint(0, 4, "Y", green);
+        assert_eq!(
+            render(&next),
+            [
+                "\x1b[1;2H",
+                &sgr(red),
+                "X",
+                "\x1b[2C",
+                &sgr(green),
+                "Y",
+                "\x1b[0m",
+            ]
+            .concat()
+        );
+
+        // Half-overwritten wide cell
+        let mut next = prev.clone();
+        next.paint(1, 2, "X", red);
+        assert_eq!(
+            render(&next),
+            ["\x1b[2;2H", &sgr(red), " ", "X", "\x1b[0m",].concat()
+        );
+    }
+
+    #[test]
+    fn diff_scroll() {
+        let rows = ["aa", "bb", "cc", "dd", "ee", "zz"];
+        let prev = Canvas::new(
+            rows.iter()
+                .map(|str| Row::new(str.to_string(), default()))
+                .collect(),
+        );
+        let render = |next: &Canvas| {
+            let mut w = Vec::new();
+            Renderer::new().diff(&mut w, &prev, next).unwrap();
+            String::from_utf8(w).unwrap()
+        };
+
+        // Scroll up in a region
+        let mut next = prev.clone();
+        next.scroll_up(0..5, 1, default());
+        next.paint(4, 0, "ff", default());
+        assert_eq!(
+            render(&next),
+            [
+                "\x1b[1;5r\x1b[1S\x1b[r",
+                "\x1b[5;1H",
+                &sgr(default()),
+                "ff",
+                "\x1b[0m"
+            ]
+            .concat()
+        );
+
+        // Scroll down everything
+        let mut next = prev.clone();
+        next.scroll_down(.., 2, default());
+        next.paint(0, 0, "xx", default());
+        next.paint(1, 0, "yy", default());
+        assert_eq!(
+            render(&next),
+            [
+                "\x1b[1;6r\x1b[2T\x1b[r",
+                "\x1b[1;1H",
+                &sgr(default()),
+                "xx",
+                "\x1b[2;1H",
+                "yy",
+                "\x1b[0m",
+            ]
+            .concat()
+        );
+
+        // Not worth scrolling
+        let mut next = prev.clone();
+        next.paint(1, 0, "!", default());
+        assert!(!render(&next).contains('S'));
+    }
+
+    #[test]
+    fn scroll_repeated_rows() {
+        let rows = (0..100)
+            .map(|i| {
+                Row::new(
+                    if i % 10 == 0 {
+                        format!("{i:04}")
+                    } else {
+                        "xxxx".into()
+                    },
+                    default(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let prev = Canvas::new(rows);
+
+        // The first row of `next` is found many times in `prev`: the nearest wins
+        let mut next = prev.clone();
+        next.scroll_up(.., 3, default());
+        assert_eq!(scroll(prev.rows(), next.rows()), Some((0..100, 3)));
+
+        let mut next = prev.clone();
+        next.scroll_down(.., 10, default());
+        assert_eq!(scroll(prev.rows(), next.rows()), Some((0..91, -10)));
+    }
+}
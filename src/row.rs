@@ -1,8 +1,9 @@
 use super::*;
+use std::ops::Range;
 use std::str::CharIndices;
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-#[derive(Clone, Default, Eq, PartialEq, Debug)]
+#[derive(Clone, Default, Eq, PartialEq, Hash, Debug)]
 pub struct Row {
     line: Line,
     spans: Spans,
@@ -10,9 +11,19 @@ pub struct Row {
 
 impl Row {
     pub fn new(string: String, style: Style) -> Self {
-        let line = Line::new(string);
+        Self::with_profile(string, style, default())
+    }
+
+    /// Returns a [`Row`] measured with `profile`.
+    pub fn with_profile(string: String, style: Style, profile: Profile) -> Self {
+        let line = Line::with_profile(string, profile);
         let width = line.width();
-        let spans = Spans::new(vec![Span { width, style }]);
+        let mut spans = Spans::default();
+        spans.push(Span {
+            width,
+            style,
+            link: None,
+        });
 
         Self { line, spans }
     }
@@ -22,34 +33,364 @@ impl Row {
         self.line.width()
     }
 
-    pub fn cells(&self) -> Cells {
+    /// Returns the [`Profile`] of this [`Row`].
+    pub fn profile(&self) -> &Profile {
+        self.line.profile()
+    }
+
+    /// Returns the [`Tabs`] of this [`Row`].
+    pub fn tabs(&self) -> &Tabs {
+        self.line.tabs()
+    }
+
+    /// Sets the [`Tabs`] of this [`Row`], for tabs added afterwards.
+    pub fn set_tabs(&mut self, tabs: Tabs) {
+        self.line.set_tabs(tabs);
+    }
+
+    /// Returns the [`Controls`] policy of this [`Row`].
+    pub fn controls(&self) -> Controls {
+        self.line.controls()
+    }
+
+    /// Sets the [`Controls`] policy of this [`Row`], for text added afterwards.
+    pub fn set_controls(&mut self, controls: Controls) {
+        self.line.set_controls(controls);
+    }
+
+    /// Returns the [`Spans`] of this [`Row`].
+    pub fn spans(&self) -> &Spans {
+        &self.spans
+    }
+
+    pub fn cells(&self) -> Cells<'_> {
         Cells::new(self)
     }
 
+    /// Returns the cells from the one covering `column`.
+    pub fn cells_at(&self, column: u16) -> Cells<'_> {
+        Cells::at(self, column)
+    }
+
+    /// Returns whether `columns` display the same in this [`Row`] and `other`, comparing
+    /// spans and strings at once.
+    ///
+    /// Equal cells can still differ, e.g. by zero-width non-joiners.
+    pub fn displays_same(&self, other: &Row, columns: Range<u16>) -> bool {
+        fn styles(row: &Row, columns: Range<u16>) -> impl Iterator<Item = (&Style, &Option<Link>)> {
+            row.spans
+                .range(columns)
+                .map(|(_, _, span)| (&span.style, &span.link))
+        }
+
+        let str = self.line.slice(columns.clone());
+
+        self.profile() == other.profile()
+            && str.is_some()
+            && str == other.line.slice(columns.clone())
+            && styles(self, columns.clone()).eq(styles(other, columns))
+    }
+
     pub fn push(&mut self, str: &str, style: Style) {
-        let width = self.line.push(str);
-        self.spans.push(Span { width, style });
+        self.push_span(str, style, None);
+    }
+
+    /// Pushes `str` as a hyperlink to `link`.
+    pub fn push_link(&mut self, str: &str, style: Style, link: Link) {
+        self.push_span(str, style, Some(link));
     }
 
     pub fn paint(&mut self, column: u16, str: &str, style: Style) {
+        self.paint_span(column, str, style, None);
+    }
+
+    /// Paints `str` at `column` as a hyperlink to `link`.
+    pub fn paint_link(&mut self, column: u16, str: &str, style: Style, link: Link) {
+        self.paint_span(column, str, style, Some(link));
+    }
+
+    /// Paints `str` at `column`, truncated to `width` with an ellipsis.
+    pub fn paint_truncated(
+        &mut self,
+        column: u16,
+        width: u16,
+        str: &str,
+        style: Style,
+        truncation: Truncation,
+    ) {
+        // Truncate what is painted, with control characters and tabs as displayed
+        let str = self.line.sanitize(str, column);
+        let str = truncate(&str, width, truncation, ELLIPSIS, self.profile());
+        self.paint(column, &str, style);
+    }
+
+    /// Returns the cell covering `column`, and whether `column` is the second half of
+    /// this (wide) cell.
+    pub fn cell_at(&self, column: u16) -> Option<(Cell<'_>, bool)> {
+        let cell = self.line.cell_at(column)?;
+        let (_, _, span) = self.spans.get(cell.column)?;
+
+        Some((
+            Cell {
+                style: span.style,
+                link: span.link.as_ref(),
+                column: cell.column,
+                width: cell.width,
+                str: cell.str,
+            },
+            cell.column != column,
+        ))
+    }
+
+    /// Erases `width` columns at `column` with spaces in `style`.
+    ///
+    /// Wide graphemes cut at the edges are replaced by spaces in their own style.
+    pub fn erase(&mut self, column: u16, width: u16, style: Style) {
+        let width = self.line.erase(column, width);
+        self.spans.paint(
+            column,
+            Span {
+                width,
+                style,
+                link: None,
+            },
+        );
+    }
+
+    /// Inserts `str` at `column` in `style`, shifting the following cells right.
+    ///
+    /// The width does not change: cells shifted past the end are dropped.
+    pub fn insert(&mut self, column: u16, str: &str, style: Style) {
+        let width = self.line.insert(column, str);
+        self.spans.insert(
+            column,
+            Span {
+                width,
+                style,
+                link: None,
+            },
+        );
+    }
+
+    /// Deletes `width` columns at `column`, shifting the following cells left.
+    ///
+    /// The width does not change: the end is filled with spaces in `fill`.
+    pub fn delete(&mut self, column: u16, width: u16, fill: Style) {
+        let width = self.line.delete(column, width);
+        self.spans.delete(
+            column,
+            Span {
+                width,
+                style: fill,
+                link: None,
+            },
+        );
+    }
+
+    /// Resizes the [`Row`] to `width`, truncating or padding with spaces in `style`.
+    pub fn resize(&mut self, width: u16, style: Style) {
+        let current = self.width();
+
+        if width < current {
+            self.line.truncate(width);
+            self.spans.truncate(width);
+        } else if width > current {
+            self.push(&" ".repeat((width - current) as usize), style);
+        }
+    }
+
+    /// Removes the zero-width non-joiners not preventing graphemes to join.
+    pub fn compact(&mut self) {
+        self.line.compact();
+    }
+
+    fn push_span(&mut self, str: &str, style: Style, link: Option<Link>) {
+        let before = self.line.width();
+        let last = before
+            .checked_sub(1)
+            .and_then(|column| self.line.cell_at(column))
+            .map(|cell| cell.column);
+        self.line.push(str);
+
+        // A grapheme joined by `str` keeps its span, even when its width changes
+        // Eg: `"❤".push("\u{FE0F}")` makes a wide heart
+        if let Some(last) = last {
+            let end = self
+                .line
+                .cell_at(last)
+                .map_or(last, |cell| cell.column + cell.width);
+
+            if end < before {
+                self.spans.truncate(end);
+            } else if end > before {
+                let (_, _, span) = self.spans.get(before - 1).expect("spans cover the line");
+                let span = Span {
+                    width: end - before,
+                    ..span.clone()
+                };
+                self.spans.push(span);
+            }
+        }
+
+        let width = self.line.width() - self.spans.width();
+        self.spans.push(Span { width, style, link });
+    }
+
+    fn paint_span(&mut self, column: u16, str: &str, style: Style, link: Option<Link>) {
         let width = self.line.paint(column, str);
-        self.spans.paint(column, Span { width, style });
+        self.spans.paint(column, Span { width, style, link });
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+/// A cell of a [`Row`], borrowed from it.
+///
+/// Two cells are equal when they display the same grapheme, with the same width,
+/// style and link, at the same column.
+#[derive(Copy, Clone, Debug)]
 pub struct Cell<'a> {
+    pub(crate) style: Style,
+    pub(crate) link: Option<&'a Link>,
+    pub(crate) column: u16,
+    pub(crate) width: u16,
+    /// The grapheme, with the zero-width non-joiners of [`Line`].
+    pub(crate) str: &'a str,
+}
+
+impl<'a> Cell<'a> {
+    /// Returns the style of this [`Cell`].
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    /// Returns the hyperlink of this [`Cell`], if any.
+    pub fn link(&self) -> Option<&'a Link> {
+        self.link
+    }
+
+    /// Returns the column of this [`Cell`].
+    pub fn column(&self) -> u16 {
+        self.column
+    }
+
+    /// Returns the width of this [`Cell`] (1 or 2).
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Returns the grapheme of this [`Cell`].
+    pub fn str(&self) -> &'a str {
+        self.str.trim_matches(ZWNJ)
+    }
+
+    /// Returns an [`OwnedCell`] with the content of this [`Cell`].
+    pub fn to_owned_cell(&self) -> OwnedCell {
+        OwnedCell {
+            style: self.style,
+            link: self.link.cloned(),
+            column: self.column,
+            width: self.width,
+            str: self.str().into(),
+        }
+    }
+}
+
+impl PartialEq for Cell<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.column == other.column
+            && self.width == other.width
+            && self.style == other.style
+            && self.link == other.link
+            && self.str() == other.str()
+    }
+}
+
+impl Eq for Cell<'_> {}
+
+impl PartialEq<OwnedCell> for Cell<'_> {
+    fn eq(&self, other: &OwnedCell) -> bool {
+        self.column == other.column
+            && self.width == other.width
+            && self.style == other.style
+            && self.link == other.link.as_ref()
+            && self.str() == other.str
+    }
+}
+
+/// An owned cell of a [`Row`], to store or compare cells in tests.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct OwnedCell {
     style: Style,
+    link: Option<Link>,
     column: u16,
     width: u16,
-    str: &'a str,
+    str: String,
+}
+
+impl OwnedCell {
+    /// Creates a new [`OwnedCell`].
+    pub fn new(column: u16, width: u16, str: impl Into<String>, style: Style) -> Self {
+        Self {
+            style,
+            link: None,
+            column,
+            width,
+            str: str.into(),
+        }
+    }
+
+    /// Sets the hyperlink of this [`OwnedCell`].
+    pub fn with_link(self, link: Link) -> Self {
+        Self {
+            link: Some(link),
+            ..self
+        }
+    }
+
+    /// Returns the style of this [`OwnedCell`].
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    /// Returns the hyperlink of this [`OwnedCell`], if any.
+    pub fn link(&self) -> Option<&Link> {
+        self.link.as_ref()
+    }
+
+    /// Returns the column of this [`OwnedCell`].
+    pub fn column(&self) -> u16 {
+        self.column
+    }
+
+    /// Returns the width of this [`OwnedCell`] (1 or 2).
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Returns the grapheme of this [`OwnedCell`].
+    pub fn str(&self) -> &str {
+        &self.str
+    }
+}
+
+impl From<Cell<'_>> for OwnedCell {
+    fn from(cell: Cell<'_>) -> Self {
+        cell.to_owned_cell()
+    }
+}
+
+impl PartialEq<Cell<'_>> for OwnedCell {
+    fn eq(&self, other: &Cell<'_>) -> bool {
+        other == self
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Cells<'a> {
     line: line::cell::Cells<'a>,
     spans: spans::Iter<'a>,
-    span: Span,
+    span: Option<&'a Span>,
+    /// Remaining width in `span`.
+    width: u16,
 }
 
 impl<'a> Cells<'a> {
@@ -57,7 +398,31 @@ impl<'a> Cells<'a> {
         Self {
             line: row.line.cells(),
             spans: row.spans.iter(),
-            span: default(),
+            span: None,
+            width: 0,
+        }
+    }
+
+    /// Returns the cells of `row` from the one covering `column`.
+    pub fn at(row: &'a Row, column: u16) -> Self {
+        let line = row.line.cells_at(column);
+        let mut spans = row.spans.iter();
+        let first = line.clone().next().map(|cell| cell.column);
+
+        // Start in the span of the first cell
+        let (span, width) = match first.and_then(|first| Some((first, row.spans.get(first)?))) {
+            Some((first, (index, start, _))) => {
+                let span = spans.nth(index);
+                (span, span.map_or(0, |span| start + span.width - first))
+            }
+            None => (spans.by_ref().last(), 0),
+        };
+
+        Self {
+            line,
+            spans,
+            span,
+            width,
         }
     }
 }
@@ -69,29 +434,212 @@ impl<'a> Iterator for Cells<'a> {
         let cell = match self.line.next() {
             Some(cell) => cell,
             None => {
-                debug_assert_eq!(self.span.width, 0);
+                debug_assert_eq!(self.width, 0);
                 debug_assert_eq!(self.spans.next(), None);
                 return None;
             }
         };
 
-        if self.span.width == 0 {
-            let span = self.spans.next().copied();
+        if self.width == 0 {
+            let span = self.spans.next();
 
             // We know `Spans` covers the entire `Line` in a `Row`
             debug_assert!(span.is_some());
-            self.span = span?;
+            let span = span?;
+            self.span = Some(span);
+            self.width = span.width;
         }
 
+        let span = self.span?;
+
         // If everything is right, this is OK
-        debug_assert!(cell.width <= self.span.width);
-        self.span.width -= cell.width;
+        debug_assert!(cell.width <= self.width);
+        self.width -= cell.width;
 
         Some(Cell {
-            style: self.span.style,
+            style: span.style,
+            link: span.link.as_ref(),
             column: cell.column,
             width: cell.width,
             str: cell.str,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const RED: Style = Style {
+        foreground: Color::Ansi(Ansi::Red),
+        background: Color::Default,
+        attributes: Attributes::empty(),
+        underline: None,
+    };
+
+    #[test]
+    fn cells() {
+        let link = Link::new("https://example.com");
+        let mut row = Row::new("abcd".into(), default());
+        row.paint(1, "🦀", RED);
+        row.paint_link(3, "!", default(), link.clone());
+
+        let cells = row.cells().map(OwnedCell::from).collect::<Vec<_>>();
+
+        assert_eq!(
+            cells,
+            [
+                OwnedCell::new(0, 1, "a", default()),
+                OwnedCell::new(1, 2, "🦀", RED),
+                OwnedCell::new(3, 1, "!", default()).with_link(link),
+            ]
+        );
+        assert!(row.cells().eq(cells.iter().cloned()));
+        assert_eq!(cells[1].str(), "🦀");
+        assert_eq!(
+            cells[2].link().map(|link| &*link.url),
+            Some("https://example.com")
+        );
+    }
+
+    #[test]
+    fn insert_delete() {
+        let mut row = Row::new("ab🦀cd".into(), default());
+
+        row.insert(1, "!!", RED);
+        assert_eq!(
+            row.cells().map(OwnedCell::from).collect::<Vec<_>>(),
+            [
+                OwnedCell::new(0, 1, "a", default()),
+                OwnedCell::new(1, 1, "!", RED),
+                OwnedCell::new(2, 1, "!", RED),
+                OwnedCell::new(3, 1, "b", default()),
+                OwnedCell::new(4, 2, "🦀", default()),
+            ]
+        );
+
+        row.delete(0, 2, RED);
+        assert_eq!(
+            row.cells().map(OwnedCell::from).collect::<Vec<_>>(),
+            [
+                OwnedCell::new(0, 1, "!", RED),
+                OwnedCell::new(1, 1, "b", default()),
+                OwnedCell::new(2, 2, "🦀", default()),
+                OwnedCell::new(4, 1, " ", RED),
+                OwnedCell::new(5, 1, " ", RED),
+            ]
+        );
+
+        // A leading spacing mark cannot join the inserted text
+        let mut row = Row::new("\u{0903}b".into(), default());
+        row.insert(0, "a", RED);
+        assert_eq!(
+            row.cells().map(OwnedCell::from).collect::<Vec<_>>(),
+            [
+                OwnedCell::new(0, 1, "a", RED),
+                OwnedCell::new(1, 1, " ", default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn push_joined() {
+        // Variation selectors and keycaps change the width of the grapheme they join
+        for (head, tail, width) in [
+            ("a❤", "\u{FE0F}", 3),
+            ("#", "\u{20E3}", 2),
+            ("⌚", "\u{FE0E}", 1),
+        ] {
+            let mut row = Row::new(head.into(), default());
+            row.push(tail, RED);
+            row.push("b", RED);
+
+            let styles = row.cells().map(|cell| cell.style()).collect::<Vec<_>>();
+            assert_eq!(row.width(), width + 1);
+            assert_eq!(styles[styles.len() - 2..], [default(), RED]);
+        }
+    }
+
+    #[test]
+    fn paint_leading_joiner() {
+        let mut row = Row::with_profile("b❤ᄀ\u{FE0F}  ".into(), default(), Profile::Wide);
+        row.paint(2, "\u{20E3}\u{200D}🦀±", default());
+
+        assert_eq!(row.width(), 6);
+        assert_eq!(
+            row.cells().map(|cell| cell.str()).collect::<Vec<_>>(),
+            ["b", "❤", "🦀", "±"]
+        );
+    }
+
+    #[test]
+    fn cells_at() {
+        let mut row = Row::new("abcdef".into(), default());
+        row.paint(1, "🦀", RED);
+
+        let cells = |column| {
+            row.cells_at(column)
+                .map(|cell| (cell.str(), cell.style() == RED))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            cells(2),
+            [("🦀", true), ("d", false), ("e", false), ("f", false)]
+        );
+        assert_eq!(cells(4), [("e", false), ("f", false)]);
+        assert_eq!(cells(6), []);
+    }
+
+    #[test]
+    fn displays_same() {
+        let row = Row::new("abcdef".into(), default());
+        let mut painted = row.clone();
+        painted.paint(1, "🦀", RED);
+
+        assert!(row.displays_same(&painted, 3..6));
+        assert!(!row.displays_same(&painted, 0..3));
+        assert!(!painted.displays_same(&painted, 2..6));
+
+        let mut styled = row.clone();
+        styled.paint(4, "e", RED);
+        assert!(!row.displays_same(&styled, 3..6));
+    }
+
+    #[test]
+    fn paint_truncated() {
+        let mut row = Row::new("..........".into(), default());
+        row.paint_truncated(1, 5, "abcdefgh", default(), Truncation::Middle);
+        row.paint_truncated(7, 2, "🦀🦀", default(), Truncation::End);
+
+        assert_eq!(
+            row.cells().map(|cell| cell.str()).collect::<String>(),
+            ".ab…gh.….."
+        );
+
+        let mut row = Row::new("..........".into(), default());
+        row.paint_truncated(0, 4, "a\tbcdefgh", default(), Truncation::End);
+        row.set_controls(Controls::Caret);
+        row.paint_truncated(4, 4, "\x1b\x1b\x1bX", default(), Truncation::End);
+
+        assert_eq!(
+            row.cells().map(|cell| cell.str()).collect::<String>(),
+            "a  …^[^….."
+        );
+    }
+
+    #[test]
+    fn equality() {
+        let mut painted = Row::new("ab".into(), default());
+        painted.paint(1, "c", default());
+        let pushed = Row::new("ac".into(), default());
+
+        // Zero-width non-joiners are not part of the cell
+        assert!(painted.cells().eq(pushed.cells()));
+
+        let (cell, _) = painted.cell_at(1).unwrap();
+        assert_eq!(cell, OwnedCell::new(1, 1, "c", default()));
+        assert_ne!(cell, OwnedCell::new(0, 1, "c", default()));
+    }
+}
@@ -1,47 +1,88 @@
 use super::*;
+use std::ops::Range;
+use std::sync::Arc;
 
-#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+#[derive(Clone, Eq, PartialEq, Hash, Default, Debug)]
 pub struct Span {
     pub width: u16,
     pub style: Style,
+    pub link: Option<Link>,
 }
 
-impl Span {}
+impl Span {
+    /// Returns whether `other` has the same style and link as this [`Span`].
+    pub fn is_mergeable(&self, other: &Span) -> bool {
+        self.style == other.style && self.link == other.link
+    }
+}
+
+/// A hyperlink (OSC 8).
+///
+/// Bytes that cannot appear in the escape sequence are percent-encoded when rendered.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Link {
+    /// The URL.
+    pub url: Arc<str>,
+    /// The id, grouping cells of the same link (e.g. when wrapped).
+    pub id: Option<Arc<str>>,
+}
+
+impl Link {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.into(),
+            id: None,
+        }
+    }
+
+    pub fn with_id(url: &str, id: &str) -> Self {
+        Self {
+            url: url.into(),
+            id: Some(id.into()),
+        }
+    }
+}
 
 pub type Iter<'a> = std::slice::Iter<'a, Span>;
-pub type IterMut<'a> = std::slice::IterMut<'a, Span>;
 pub type Scan<'a> = std::iter::Scan<
     std::iter::Enumerate<Iter<'a>>,
     u16,
     fn(&mut u16, (usize, &'a Span)) -> Option<(usize, u16, &'a Span)>,
 >;
-pub type ScanMut<'a> = std::iter::Scan<
-    std::iter::Enumerate<IterMut<'a>>,
-    u16,
-    fn(&mut u16, (usize, &'a mut Span)) -> Option<(usize, u16, &'a mut Span)>,
->;
 
-#[derive(Clone, Eq, PartialEq, Default, Debug)]
-pub struct Spans(Vec<Span>);
+/// Consecutive [`Span`]s.
+///
+/// Along with the spans, [`Spans`] stores the cumulative width at the end of
+/// each span, so that column lookups are binary searches.
+#[derive(Clone, Eq, PartialEq, Hash, Default, Debug)]
+pub struct Spans {
+    spans: Vec<Span>,
+    /// The end column of each span.
+    ends: Vec<u16>,
+}
 
 impl Spans {
     pub fn new(spans: Vec<Span>) -> Self {
-        Self(spans)
-    }
+        let ends = spans
+            .iter()
+            .scan(0, |end, span| {
+                *end += span.width;
+                Some(*end)
+            })
+            .collect();
 
-    pub fn width(&self) -> u16 {
-        self.iter().map(|span| span.width).sum()
+        Self { spans, ends }
     }
 
-    pub fn iter(&self) -> Iter {
-        self.0.iter()
+    pub fn width(&self) -> u16 {
+        self.ends.last().copied().unwrap_or_default()
     }
 
-    pub fn iter_mut(&mut self) -> IterMut {
-        self.0.iter_mut()
+    pub fn iter(&self) -> Iter<'_> {
+        self.spans.iter()
     }
 
-    pub fn scan(&self) -> Scan {
+    pub fn scan(&self) -> Scan<'_> {
         self.iter().enumerate().scan(0, |column, (index, span)| {
             let c = *column;
             *column += span.width;
@@ -49,118 +90,212 @@ impl Spans {
         })
     }
 
-    pub fn scan_mut(&mut self) -> ScanMut {
-        self.iter_mut()
-            .enumerate()
-            .scan(0, |column, (index, span)| {
-                let c = *column;
-                *column += span.width;
-                Some((index, c, span))
-            })
+    /// Returns the index, starting column and span covering `column`.
+    pub fn get(&self, column: u16) -> Option<(usize, u16, &Span)> {
+        let index = self.find(column)?;
+        let span = &self.spans[index];
+
+        Some((index, self.ends[index] - span.width, span))
     }
 
-    // pub fn get(&self, column: u16) -> Option<(usize, u16, &Span)> {
-    //     self.iter()
-    //         .enumerate()
-    //         .scan(0, |c, (i, span)| {
-    //             let column = *c;
-    //             *c += span.width;
-    //             Some((i, column, span))
-    //         })
-    //         .find(|(i, c, span)| column < c + span.width)
-    // }
-
-    // pub fn get_mut(&mut self, column: u16) -> Option<(usize, u16, &mut Span)> {
-    //     self.iter_mut()
-    //         .enumerate()
-    //         .scan(0, |c, (i, span)| {
-    //             let column = *c;
-    //             *c += span.width;
-    //             Some((i, column, span))
-    //         })
-    //         .find(|(i, c, span)| column < c + span.width)
-    // }
+    /// Returns the indexes, starting columns and spans covering `columns`.
+    pub fn range(&self, columns: Range<u16>) -> impl Iterator<Item = (usize, u16, &Span)> + '_ {
+        let columns = columns.start..columns.end.min(self.width());
+        let indexes = if columns.is_empty() {
+            0..0
+        } else {
+            self.ends.partition_point(|end| *end <= columns.start)
+                ..self.ends.partition_point(|end| *end < columns.end) + 1
+        };
+
+        self.spans[indexes.clone()]
+            .iter()
+            .zip(indexes)
+            .map(|(span, index)| (index, self.ends[index] - span.width, span))
+    }
+
+    /// Returns the index of the span covering `column`.
+    fn find(&self, column: u16) -> Option<usize> {
+        let index = self.ends.partition_point(|end| *end <= column);
+
+        (index < self.spans.len()).then_some(index)
+    }
 
+    /// Pushes `span`, merging it with the last span when possible.
     pub fn push(&mut self, span: Span) {
-        self.0.push(span);
+        if span.width == 0 {
+            return;
+        }
+
+        let end = self.width() + span.width;
+
+        match self.spans.last_mut() {
+            Some(last) if last.is_mergeable(&span) => {
+                last.width += span.width;
+                *self.ends.last_mut().unwrap() = end;
+            }
+            _ => {
+                self.spans.push(span);
+                self.ends.push(end);
+            }
+        }
     }
 
-    // TODO handle same consecutive styles
-    pub fn paint(&mut self, column: u16, span: Span) {
-        let mut last = (0, 0, &default());
-        let mut scan = self
-            .scan()
-            .inspect(|(i, c, s)| last = (*i, *c, *s))
-            .skip_while(|(i, c, s)| c + s.width <= column);
+    /// Removes zero-width spans and merges consecutive spans with the same style and link.
+    pub fn normalize(&mut self) {
+        self.spans.retain(|span| span.width != 0);
+        self.spans.dedup_by(|span, prev| {
+            let mergeable = prev.is_mergeable(span);
+            if mergeable {
+                prev.width += span.width;
+            }
+            mergeable
+        });
 
-        let Some(start) = scan.next() else { return; };
+        *self = Self::new(std::mem::take(&mut self.spans));
+    }
 
-        let end = if column + span.width <= start.1 + start.2.width {
-            start
-        } else {
-            scan.skip_while(|(i, c, s)| c + s.width < column + span.width)
-                .next()
-                .unwrap_or(last)
-        };
+    /// Normalizes spans in `range`, the rest being already normalized.
+    fn normalize_range(&mut self, range: Range<usize>) {
+        let mut i = range.start.max(1);
+        let mut end = range.end.min(self.spans.len());
+
+        while i < end {
+            if self.spans[i].width == 0 {
+                self.spans.remove(i);
+                self.ends.remove(i);
+                end -= 1;
+            } else if self.spans[i - 1].is_mergeable(&self.spans[i]) {
+                self.spans[i - 1].width += self.spans[i].width;
+                self.spans.remove(i);
+                self.ends.remove(i - 1);
+                end -= 1;
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Truncates the [`Spans`] to `width`.
+    pub fn truncate(&mut self, width: u16) {
+        if width == 0 {
+            self.spans.clear();
+            self.ends.clear();
+            return;
+        }
+
+        let Some((index, column, _)) = self.get(width - 1) else { return; };
+
+        self.spans.truncate(index + 1);
+        self.ends.truncate(index + 1);
+        self.spans[index].width = width - column;
+        self.ends[index] = width;
+    }
+
+    /// Paints `span` at `column`, keeping spans normalized.
+    pub fn paint(&mut self, column: u16, span: Span) {
+        // Nothing to do when `column` is outside the `Spans`
+        if span.width == 0 || column >= self.width() {
+            return;
+        }
 
         // Not growing in width!
         let span = {
             let mut span = span;
-            span.width = span.width.min(last.1 + last.2.width - column);
+            span.width = span.width.min(self.width() - column);
             span
         };
 
-        //     ___ ... ___
-        // ___ ___ ... ___ ___
-        if start.1 == column && end.1 + end.2.width == column + span.width {
-            self.0.splice(start.0..=end.0, [span]);
-        }
-        //     ___ ... __
+        let Some(start) = self.find(column) else { return; };
+        let Some(end) = self.find(column + span.width - 1) else { return; };
+        let start_column = self.ends[start] - self.spans[start].width;
+        let end_column = self.ends[end];
+
+        // Splitting keeps styles and links
+        //      __ ... __
         // ___ ___ ... ___ ___
-        else if start.1 == column {
-            self.0.splice(
-                start.0..=end.0,
-                [
-                    span,
-                    Span {
-                        width: (end.1 + end.2.width) - (column + span.width),
-                        style: end.2.style,
-                    },
-                ],
-            );
+        let before = (start_column < column).then(|| Span {
+            width: column - start_column,
+            ..self.spans[start].clone()
+        });
+        let after = (column + span.width < end_column).then(|| Span {
+            width: end_column - (column + span.width),
+            ..self.spans[end].clone()
+        });
+
+        // The total width does not change, so do the following ends
+        let ends = [before.as_ref(), Some(&span), after.as_ref()]
+            .into_iter()
+            .flatten()
+            .scan(start_column, |end, span| {
+                *end += span.width;
+                Some(*end)
+            });
+        self.ends.splice(start..=end, ends);
+        self.spans
+            .splice(start..=end, before.into_iter().chain([span]).chain(after));
+
+        // Normalize around the spliced spans
+        self.normalize_range(start.saturating_sub(1)..start + 4);
+    }
+
+    /// Inserts `span` at `column`, shifting the following spans right.
+    ///
+    /// The width does not change: spans shifted past the end are dropped.
+    pub fn insert(&mut self, column: u16, span: Span) {
+        let width = self.width();
+
+        // Nothing to do when `column` is outside the `Spans`
+        if span.width == 0 || column >= width {
+            return;
         }
-        //      __ ... ___
-        // ___ ___ ... ___ ___
-        else if end.1 + end.2.width == column + span.width {
-            self.0.splice(
-                start.0..=end.0,
-                [
-                    Span {
-                        width: column - start.1,
-                        style: start.2.style,
-                    },
-                    span,
-                ],
-            );
+
+        let tail = self.split_off(column);
+        self.push(span);
+        tail.into_iter().for_each(|span| self.push(span));
+        self.truncate(width);
+    }
+
+    /// Deletes `fill.width` columns at `column`, shifting the following spans left,
+    /// and fills the end with `fill`.
+    pub fn delete(&mut self, column: u16, fill: Span) {
+        let width = self.width();
+
+        // Nothing to do when `column` is outside the `Spans`
+        if fill.width == 0 || column >= width {
+            return;
         }
-        //      __ ... __
-        // ___ ___ ... ___ ___
-        else {
-            self.0.splice(
-                start.0..=end.0,
-                [
-                    Span {
-                        width: column - start.1,
-                        style: start.2.style,
-                    },
-                    span,
-                    Span {
-                        width: (end.1 + end.2.width) - (column + span.width),
-                        style: end.2.style,
-                    },
-                ],
-            );
+
+        // Not growing in width!
+        let fill = Span {
+            width: fill.width.min(width - column),
+            ..fill
+        };
+
+        let tail = self.split_off(column + fill.width);
+        self.split_off(column);
+        tail.into_iter().for_each(|span| self.push(span));
+        self.push(fill);
+    }
+
+    /// Splits the [`Spans`] at `column` and returns the spans after.
+    fn split_off(&mut self, column: u16) -> Vec<Span> {
+        let Some((index, start, _)) = self.get(column) else { return Vec::new(); };
+
+        self.ends.truncate(index);
+        let mut tail = self.spans.split_off(index);
+
+        // Splitting keeps styles and links
+        if start < column {
+            self.spans.push(Span {
+                width: column - start,
+                ..tail[0].clone()
+            });
+            self.ends.push(column);
+            tail[0].width -= column - start;
         }
+
+        tail
     }
 }
 
@@ -169,10 +304,10 @@ mod tests {
     use super::*;
     use test_case::test_case;
 
-    const RED: Color = Color { r: 255, g: 0, b: 0 };
-    const GREEN: Color = Color { r: 0, g: 255, b: 0 };
-    const BLUE: Color = Color { r: 0, g: 0, b: 255 };
-    const YELLOW: Color = Color {
+    const RED: Color = Color::Rgb { r: 255, g: 0, b: 0 };
+    const GREEN: Color = Color::Rgb { r: 0, g: 255, b: 0 };
+    const BLUE: Color = Color::Rgb { r: 0, g: 0, b: 255 };
+    const YELLOW: Color = Color::Rgb {
         r: 255,
         g: 255,
         b: 0,
@@ -185,11 +320,12 @@ mod tests {
                 foreground: color,
                 ..default()
             },
+            link: None,
         }
     }
 
     fn new_spans(spans: impl IntoIterator<Item = (u16, Color)>) -> Spans {
-        Spans(
+        Spans::new(
             spans
                 .into_iter()
                 .map(|(width, color)| new_span(width, color))
@@ -197,6 +333,17 @@ mod tests {
         )
     }
 
+    fn colors(spans: Spans) -> Vec<(u16, Color)> {
+        // `ends` must be in sync with `spans`
+        assert_eq!(spans.ends, Spans::new(spans.spans.clone()).ends);
+
+        spans
+            .spans
+            .into_iter()
+            .map(|span| (span.width, span.style.foreground))
+            .collect()
+    }
+
     #[test]
     fn scan() {
         let spans = new_spans([(1, RED), (2, GREEN), (3, BLUE)]);
@@ -210,16 +357,136 @@ mod tests {
                 (2, 3, &new_span(3, BLUE)),
             ]
         );
+    }
 
-        let mut spans = new_spans([(1, RED), (2, GREEN), (3, BLUE)]);
-        let scan = spans.scan_mut().collect::<Vec<_>>();
+    #[test]
+    fn get() {
+        let spans = new_spans([(1, RED), (2, GREEN), (3, BLUE)]);
+        let get = |column| spans.get(column).map(|(index, column, _)| (index, column));
+
+        assert_eq!(get(0), Some((0, 0)));
+        assert_eq!(get(1), Some((1, 1)));
+        assert_eq!(get(2), Some((1, 1)));
+        assert_eq!(get(3), Some((2, 3)));
+        assert_eq!(get(5), Some((2, 3)));
+        assert_eq!(get(6), None);
+    }
+
+    #[test]
+    fn range() {
+        let spans = new_spans([(1, RED), (2, GREEN), (3, BLUE)]);
+        let range = |columns| {
+            spans
+                .range(columns)
+                .map(|(index, column, _)| (index, column))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(range(0..0), []);
+        assert_eq!(range(0..1), [(0, 0)]);
+        assert_eq!(range(1..3), [(1, 1)]);
+        assert_eq!(range(2..4), [(1, 1), (2, 3)]);
+        assert_eq!(range(0..10), [(0, 0), (1, 1), (2, 3)]);
+        assert_eq!(range(6..10), []);
+    }
+
+    #[test]
+    fn push() {
+        let mut spans = Spans::default();
+        spans.push(new_span(2, RED));
+        spans.push(new_span(0, GREEN));
+        spans.push(new_span(3, RED));
+        spans.push(new_span(1, BLUE));
+
+        assert_eq!(spans, new_spans([(5, RED), (1, BLUE)]));
+    }
+
+    #[test]
+    fn normalize() {
+        let mut spans = new_spans([
+            (0, RED),
+            (1, RED),
+            (0, GREEN),
+            (2, RED),
+            (2, BLUE),
+            (1, BLUE),
+        ]);
+        spans.normalize();
+
+        assert_eq!(spans, new_spans([(3, RED), (3, BLUE)]));
+    }
+
+    #[test_case([(3, RED), (3, GREEN)], 0 => Vec::<(u16, Color)>::new()  ; "Truncate at 0")]
+    #[test_case([(3, RED), (3, GREEN)], 2 => vec![(2, RED)]              ; "Truncate at 2")]
+    #[test_case([(3, RED), (3, GREEN)], 3 => vec![(3, RED)]              ; "Truncate at 3")]
+    #[test_case([(3, RED), (3, GREEN)], 4 => vec![(3, RED), (1, GREEN)]  ; "Truncate at 4")]
+    #[test_case([(3, RED), (3, GREEN)], 7 => vec![(3, RED), (3, GREEN)]  ; "Truncate at 7")]
+    fn truncate(initial: impl IntoIterator<Item = (u16, Color)>, width: u16) -> Vec<(u16, Color)> {
+        let mut spans = new_spans(initial);
+        spans.truncate(width);
+
+        colors(spans)
+    }
+
+    #[test_case([(3, RED), (3, GREEN)], 0, (2, BLUE) => vec![(2, BLUE), (3, RED), (1, GREEN)]; "Insert at 0")]
+    #[test_case([(3, RED), (3, GREEN)], 1, (2, BLUE) => vec![(1, RED), (2, BLUE), (2, RED), (1, GREEN)]; "Insert at 1")]
+    #[test_case([(3, RED), (3, GREEN)], 3, (1, RED)  => vec![(4, RED), (2, GREEN)]; "Insert merging")]
+    #[test_case([(3, RED), (3, GREEN)], 4, (9, BLUE) => vec![(3, RED), (1, GREEN), (2, BLUE)]; "Insert overflowing")]
+    #[test_case([(3, RED), (3, GREEN)], 6, (1, BLUE) => vec![(3, RED), (3, GREEN)]; "Insert outside")]
+    fn insert(
+        initial: impl IntoIterator<Item = (u16, Color)>,
+        column: u16,
+        (width, color): (u16, Color),
+    ) -> Vec<(u16, Color)> {
+        let mut spans = new_spans(initial);
+        spans.insert(column, new_span(width, color));
+
+        colors(spans)
+    }
+
+    #[test_case([(3, RED), (3, GREEN)], 0, (2, BLUE) => vec![(1, RED), (3, GREEN), (2, BLUE)]; "Delete at 0")]
+    #[test_case([(3, RED), (3, GREEN)], 2, (2, BLUE) => vec![(2, RED), (2, GREEN), (2, BLUE)]; "Delete across")]
+    #[test_case([(3, RED), (3, GREEN)], 3, (1, GREEN) => vec![(3, RED), (3, GREEN)]; "Delete merging")]
+    #[test_case([(3, RED), (3, GREEN)], 4, (9, BLUE) => vec![(3, RED), (1, GREEN), (2, BLUE)]; "Delete to end")]
+    #[test_case([(3, RED), (3, GREEN)], 6, (1, BLUE) => vec![(3, RED), (3, GREEN)]; "Delete outside")]
+    fn delete(
+        initial: impl IntoIterator<Item = (u16, Color)>,
+        column: u16,
+        (width, color): (u16, Color),
+    ) -> Vec<(u16, Color)> {
+        let mut spans = new_spans(initial);
+        spans.delete(column, new_span(width, color));
+
+        colors(spans)
+    }
+
+    #[test]
+    fn paint_link() {
+        let link = Link::new("https://example.com");
+        let mut spans = new_spans([(6, RED)]);
+        spans.paint(
+            2,
+            Span {
+                link: Some(link.clone()),
+                ..new_span(3, GREEN)
+            },
+        );
+        spans.paint(3, new_span(1, BLUE));
 
         assert_eq!(
-            scan,
+            spans.spans,
             [
-                (0, 0, &mut new_span(1, RED)),
-                (1, 1, &mut new_span(2, GREEN)),
-                (2, 3, &mut new_span(3, BLUE)),
+                new_span(2, RED),
+                Span {
+                    link: Some(link.clone()),
+                    ..new_span(1, GREEN)
+                },
+                new_span(1, BLUE),
+                Span {
+                    link: Some(link),
+                    ..new_span(1, GREEN)
+                },
+                new_span(1, RED),
             ]
         );
     }
@@ -378,6 +645,34 @@ mod tests {
         => vec![(3, RED), (6, YELLOW)];
         "Test 22"
     )]
+    #[test_case(
+        //     ___
+        // ___ ___ ___
+        [(3, RED), (3, GREEN), (3, BLUE)], 3, (3, RED)
+        => vec![(6, RED), (3, BLUE)];
+        "Test 23"
+    )]
+    #[test_case(
+        //     ___
+        // ___ ___ ___
+        [(3, RED), (3, GREEN), (3, RED)], 3, (3, RED)
+        => vec![(9, RED)];
+        "Test 24"
+    )]
+    #[test_case(
+        //  _
+        // ___ ___
+        [(3, RED), (3, GREEN)], 1, (1, RED)
+        => vec![(3, RED), (3, GREEN)];
+        "Test 25"
+    )]
+    #[test_case(
+        //   __
+        // ___ ___
+        [(3, RED), (3, GREEN)], 2, (2, GREEN)
+        => vec![(2, RED), (4, GREEN)];
+        "Test 26"
+    )]
     fn paint(
         initial: impl IntoIterator<Item = (u16, Color)>,
         column: u16,
@@ -389,10 +684,6 @@ mod tests {
         spans.paint(column, new_span(span.0, span.1));
         assert_eq!(spans.width(), width);
 
-        spans
-            .0
-            .into_iter()
-            .map(|span| (span.width, span.style.foreground))
-            .collect()
+        colors(spans)
     }
 }
@@ -1,13 +1,73 @@
 use super::*;
 
-#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
-pub struct Color {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
+/// A terminal color.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Default, Debug)]
+pub enum Color {
+    /// The terminal's default color.
+    #[default]
+    Default,
+    /// One of the 16 named colors, following the terminal's theme.
+    Ansi(Ansi),
+    /// An index in the 256-color palette.
+    Indexed(u8),
+    /// A truecolor.
+    Rgb { r: u8, g: u8, b: u8 },
+}
+
+/// The 16 named ANSI colors.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Ansi {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+}
+
+impl Ansi {
+    /// All named colors, in palette order.
+    pub const ALL: [Self; 16] = [
+        Self::Black,
+        Self::Red,
+        Self::Green,
+        Self::Yellow,
+        Self::Blue,
+        Self::Magenta,
+        Self::Cyan,
+        Self::White,
+        Self::BrightBlack,
+        Self::BrightRed,
+        Self::BrightGreen,
+        Self::BrightYellow,
+        Self::BrightBlue,
+        Self::BrightMagenta,
+        Self::BrightCyan,
+        Self::BrightWhite,
+    ];
+
+    /// Returns the index of this color in the 256-color palette.
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the named color at `index` in the 256-color palette, if any.
+    pub fn from_index(index: u8) -> Option<Self> {
+        Self::ALL.get(index as usize).copied()
+    }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Default, Debug)]
 pub enum UnderlineStyle {
     #[default]
     Single,
@@ -17,18 +77,70 @@ pub enum UnderlineStyle {
     Dash,
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Default, Debug)]
 pub struct Underline {
     pub style: UnderlineStyle,
     pub color: Color,
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+/// A set of text attributes.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Default, Debug)]
+pub struct Attributes(u8);
+
+impl Attributes {
+    pub const BOLD: Self = Self(1 << 0);
+    pub const DIM: Self = Self(1 << 1);
+    pub const ITALIC: Self = Self(1 << 2);
+    pub const BLINK: Self = Self(1 << 3);
+    pub const REVERSE: Self = Self(1 << 4);
+    pub const HIDDEN: Self = Self(1 << 5);
+    pub const STRIKE: Self = Self(1 << 6);
+    pub const OVERLINE: Self = Self(1 << 7);
+
+    /// Returns an empty set of attributes.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Returns whether this set is empty.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns whether this set contains all of `other`.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Adds `other` to this set.
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    /// Removes `other` from this set.
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+}
+
+impl std::ops::BitOr for Attributes {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl std::ops::BitOrAssign for Attributes {
+    fn bitor_assign(&mut self, other: Self) {
+        self.insert(other);
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Default, Debug)]
 pub struct Style {
     pub foreground: Color,
     pub background: Color,
-    pub bold: bool,
-    pub italic: bool,
-    pub strike: bool,
+    pub attributes: Attributes,
     pub underline: Option<Underline>,
 }
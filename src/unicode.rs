@@ -1,7 +1,350 @@
 pub use cells::*;
 
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::ops::RangeInclusive;
+use std::sync::{Arc, RwLock};
 use unicode_segmentation::Graphemes;
 
+/// The global [`Profile`].
+static GLOBAL: RwLock<Profile> = RwLock::new(Profile::Narrow);
+
+/// Measures graphemes like a terminal does.
+pub trait WidthOracle: Debug + Send + Sync {
+    /// Returns the displayed width of `grapheme` in columns.
+    ///
+    /// Widths above 2 are clamped.
+    fn grapheme_width(&self, grapheme: &str) -> usize;
+}
+
+/// How a terminal displays graphemes.
+///
+/// The default profile is the global one, initially [`Profile::Narrow`].
+#[derive(Clone, Debug)]
+pub enum Profile {
+    /// Emoji presentation, East Asian ambiguous-width characters in one column,
+    /// as in most modern terminals (kitty, WezTerm, ...).
+    Narrow,
+    /// Emoji presentation, East Asian ambiguous-width characters in two columns,
+    /// as in CJK terminals.
+    Wide,
+    /// The sum of the widths of the characters, ignoring emoji presentation,
+    /// as in `wcwidth`-based terminals (xterm, VTE, ...).
+    Legacy,
+    /// A custom [`WidthOracle`], shared by the lines measured with it.
+    Custom(Arc<dyn WidthOracle>),
+}
+
+impl Profile {
+    /// Returns a [`Profile::Custom`] for `oracle`.
+    pub fn custom(oracle: impl WidthOracle + 'static) -> Self {
+        Self::Custom(Arc::new(oracle))
+    }
+
+    /// Returns the global [`Profile`].
+    pub fn global() -> Self {
+        GLOBAL
+            .read()
+            .unwrap_or_else(|error| error.into_inner())
+            .clone()
+    }
+
+    /// Sets the global [`Profile`].
+    ///
+    /// Only affects lines created afterwards.
+    pub fn set_global(self) {
+        *GLOBAL.write().unwrap_or_else(|error| error.into_inner()) = self;
+    }
+
+    /// Returns the displayed width of `str` in columns.
+    pub fn width(&self, str: &str) -> usize {
+        str.graphemes()
+            .map(|grapheme| grapheme_width(grapheme, self))
+            .sum()
+    }
+
+    /// Returns the displayed width of `char` in columns.
+    fn char_width(&self, char: char) -> usize {
+        match self {
+            Self::Wide => unicode_width::UnicodeWidthChar::width_cjk(char),
+            _ => unicode_width::UnicodeWidthChar::width(char),
+        }
+        .unwrap_or(0)
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::global()
+    }
+}
+
+impl PartialEq for Profile {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Narrow, Self::Narrow)
+            | (Self::Wide, Self::Wide)
+            | (Self::Legacy, Self::Legacy) => true,
+            (Self::Custom(a), Self::Custom(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Profile {}
+
+impl Hash for Profile {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+
+        if let Self::Custom(oracle) = self {
+            Arc::as_ptr(oracle).cast::<()>().hash(state);
+        }
+    }
+}
+
+impl WidthOracle for Profile {
+    fn grapheme_width(&self, grapheme: &str) -> usize {
+        grapheme_width(grapheme, self)
+    }
+}
+
+/// Text presentation selector.
+const VS15: char = '\u{FE0E}';
+/// Emoji presentation selector.
+const VS16: char = '\u{FE0F}';
+/// Combining enclosing keycap.
+const KEYCAP: char = '\u{20E3}';
+
+/// Ranges of characters with emoji variation sequences, from Unicode
+/// `emoji-variation-sequences.txt`.
+#[rustfmt::skip]
+const EMOJI_VARIATIONS: &[(u32, u32)] = &[
+    (0x0023, 0x0023), (0x002A, 0x002A), (0x0030, 0x0039), (0x00A9, 0x00A9),
+    (0x00AE, 0x00AE), (0x203C, 0x203C), (0x2049, 0x2049), (0x2122, 0x2122),
+    (0x2139, 0x2139), (0x2194, 0x2199), (0x21A9, 0x21AA), (0x231A, 0x231B),
+    (0x2328, 0x2328), (0x23CF, 0x23CF), (0x23E9, 0x23EA), (0x23ED, 0x23EF),
+    (0x23F1, 0x23F3), (0x23F8, 0x23FA), (0x24C2, 0x24C2), (0x25AA, 0x25AB),
+    (0x25B6, 0x25B6), (0x25C0, 0x25C0), (0x25FB, 0x25FE), (0x2600, 0x2604),
+    (0x260E, 0x260E), (0x2611, 0x2611), (0x2614, 0x2615), (0x2618, 0x2618),
+    (0x261D, 0x261D), (0x2620, 0x2620), (0x2622, 0x2623), (0x2626, 0x2626),
+    (0x262A, 0x262A), (0x262E, 0x262F), (0x2638, 0x263A), (0x2640, 0x2640),
+    (0x2642, 0x2642), (0x2648, 0x2653), (0x265F, 0x2660), (0x2663, 0x2663),
+    (0x2665, 0x2666), (0x2668, 0x2668), (0x267B, 0x267B), (0x267E, 0x267F),
+    (0x2692, 0x2697), (0x2699, 0x2699), (0x269B, 0x269C), (0x26A0, 0x26A1),
+    (0x26A7, 0x26A7), (0x26AA, 0x26AB), (0x26B0, 0x26B1), (0x26BD, 0x26BE),
+    (0x26C4, 0x26C5), (0x26C8, 0x26C8), (0x26CF, 0x26CF), (0x26D1, 0x26D1),
+    (0x26D3, 0x26D4), (0x26E9, 0x26EA), (0x26F0, 0x26F5), (0x26F7, 0x26FA),
+    (0x26FD, 0x26FD), (0x2702, 0x2702), (0x2705, 0x2705), (0x2708, 0x270D),
+    (0x270F, 0x270F), (0x2712, 0x2712), (0x2714, 0x2714), (0x2716, 0x2716),
+    (0x271D, 0x271D), (0x2721, 0x2721), (0x2733, 0x2734), (0x2744, 0x2744),
+    (0x2747, 0x2747), (0x274C, 0x274C), (0x274E, 0x274E), (0x2753, 0x2755),
+    (0x2757, 0x2757), (0x2763, 0x2764), (0x2795, 0x2797), (0x27A1, 0x27A1),
+    (0x27B0, 0x27B0), (0x27BF, 0x27BF), (0x2934, 0x2935), (0x2B05, 0x2B07),
+    (0x2B1B, 0x2B1C), (0x2B50, 0x2B50), (0x2B55, 0x2B55), (0x3030, 0x3030),
+    (0x303D, 0x303D), (0x3297, 0x3297), (0x3299, 0x3299), (0x1F004, 0x1F004),
+    (0x1F170, 0x1F171), (0x1F17E, 0x1F17F), (0x1F202, 0x1F202), (0x1F21A, 0x1F21A),
+    (0x1F22F, 0x1F22F), (0x1F237, 0x1F237), (0x1F30D, 0x1F30F), (0x1F315, 0x1F315),
+    (0x1F31C, 0x1F31C), (0x1F321, 0x1F321), (0x1F324, 0x1F32C), (0x1F336, 0x1F336),
+    (0x1F378, 0x1F378), (0x1F37D, 0x1F37D), (0x1F393, 0x1F393), (0x1F396, 0x1F397),
+    (0x1F399, 0x1F39B), (0x1F39E, 0x1F39F), (0x1F3A7, 0x1F3A7), (0x1F3AC, 0x1F3AE),
+    (0x1F3C2, 0x1F3C2), (0x1F3C4, 0x1F3C4), (0x1F3C6, 0x1F3C6), (0x1F3CA, 0x1F3CE),
+    (0x1F3D4, 0x1F3E0), (0x1F3ED, 0x1F3ED), (0x1F3F3, 0x1F3F3), (0x1F3F5, 0x1F3F5),
+    (0x1F3F7, 0x1F3F7), (0x1F408, 0x1F408), (0x1F415, 0x1F415), (0x1F41F, 0x1F41F),
+    (0x1F426, 0x1F426), (0x1F43F, 0x1F43F), (0x1F441, 0x1F442), (0x1F446, 0x1F449),
+    (0x1F44D, 0x1F44E), (0x1F453, 0x1F453), (0x1F46A, 0x1F46A), (0x1F47D, 0x1F47D),
+    (0x1F4A3, 0x1F4A3), (0x1F4B0, 0x1F4B0), (0x1F4B3, 0x1F4B3), (0x1F4BB, 0x1F4BB),
+    (0x1F4BF, 0x1F4BF), (0x1F4CB, 0x1F4CB), (0x1F4DA, 0x1F4DA), (0x1F4DF, 0x1F4DF),
+    (0x1F4E4, 0x1F4E6), (0x1F4EA, 0x1F4ED), (0x1F4F7, 0x1F4F7), (0x1F4F9, 0x1F4FB),
+    (0x1F4FD, 0x1F4FD), (0x1F508, 0x1F508), (0x1F50D, 0x1F50D), (0x1F512, 0x1F513),
+    (0x1F549, 0x1F54A), (0x1F550, 0x1F567), (0x1F56F, 0x1F570), (0x1F573, 0x1F579),
+    (0x1F587, 0x1F587), (0x1F58A, 0x1F58D), (0x1F590, 0x1F590), (0x1F5A5, 0x1F5A5),
+    (0x1F5A8, 0x1F5A8), (0x1F5B1, 0x1F5B2), (0x1F5BC, 0x1F5BC), (0x1F5C2, 0x1F5C4),
+    (0x1F5D1, 0x1F5D3), (0x1F5DC, 0x1F5DE), (0x1F5E1, 0x1F5E1), (0x1F5E3, 0x1F5E3),
+    (0x1F5E8, 0x1F5E8), (0x1F5EF, 0x1F5EF), (0x1F5F3, 0x1F5F3), (0x1F5FA, 0x1F5FA),
+    (0x1F610, 0x1F610), (0x1F687, 0x1F687), (0x1F68D, 0x1F68D), (0x1F691, 0x1F691),
+    (0x1F694, 0x1F694), (0x1F698, 0x1F698), (0x1F6AD, 0x1F6AD), (0x1F6B2, 0x1F6B2),
+    (0x1F6B9, 0x1F6BA), (0x1F6BC, 0x1F6BC), (0x1F6CB, 0x1F6CB), (0x1F6CD, 0x1F6CF),
+    (0x1F6E0, 0x1F6E5), (0x1F6E9, 0x1F6E9), (0x1F6F0, 0x1F6F0), (0x1F6F3, 0x1F6F3),
+];
+
+/// Returns whether `char` has emoji variation sequences.
+fn emoji_variation(char: char) -> bool {
+    let char = char as u32;
+
+    EMOJI_VARIATIONS
+        .binary_search_by(|(start, end)| {
+            if *end < char {
+                std::cmp::Ordering::Less
+            } else if *start > char {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+/// Returns the displayed width of `grapheme` in columns (0, 1 or 2) with `profile`.
+///
+/// Except in [`Profile::Legacy`] and [`Profile::Custom`], the width is the one of the
+/// first character, following emoji presentation:
+/// - `VS16` displays characters with emoji variation sequences as emoji (2 columns),
+/// - `VS15` displays them as text (at most 1 column),
+/// - keycaps display digits, `#` and `*` as emoji (2 columns),
+/// - a pair of regional indicators displays as a flag (2 columns).
+///
+/// Other characters (combining marks, zero-width joiners and what they join) do
+/// not add to the width.
+pub fn grapheme_width(grapheme: &str, profile: &Profile) -> usize {
+    match profile {
+        Profile::Custom(oracle) => return oracle.grapheme_width(grapheme).min(2),
+        Profile::Legacy => {
+            return grapheme
+                .chars()
+                .map(|char| profile.char_width(char))
+                .sum::<usize>()
+                .min(2)
+        }
+        Profile::Narrow | Profile::Wide => {}
+    }
+
+    let mut chars = grapheme.chars();
+    let Some(first) = chars.next() else { return 0; };
+    let width = profile.char_width(first);
+
+    // Zero-width graphemes (controls, lone combining marks, ...)
+    if width == 0 {
+        return 0;
+    }
+
+    let second = chars.next();
+    let regional = |char: char| ('\u{1F1E6}'..='\u{1F1FF}').contains(&char);
+
+    match second {
+        Some(VS15) if emoji_variation(first) => 1,
+        Some(VS16) if emoji_variation(first) => 2,
+        Some(KEYCAP) if matches!(first, '0'..='9' | '#' | '*') => 2,
+        Some(second) if regional(first) && regional(second) => 2,
+        _ => width.min(2),
+    }
+}
+
+/// A [`WidthOracle`] table of grapheme widths, e.g. measured on a terminal.
+///
+/// Graphemes missing from the table are measured with a fallback [`Profile`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct WidthTable {
+    graphemes: HashMap<String, usize>,
+    /// Ranges of first characters.
+    ranges: Vec<(RangeInclusive<char>, usize)>,
+    fallback: Profile,
+}
+
+impl WidthTable {
+    /// Returns an empty [`WidthTable`] falling back to `fallback`.
+    pub fn new(fallback: Profile) -> Self {
+        Self {
+            graphemes: HashMap::new(),
+            ranges: Vec::new(),
+            fallback,
+        }
+    }
+
+    /// Parses a [`WidthTable`] falling back to `fallback`.
+    ///
+    /// Each line maps a grapheme (code points in hexadecimal) or a range of first
+    /// characters to a width, as in Unicode data files:
+    ///
+    /// ```text
+    /// # Comment
+    /// 2764 FE0F  ; 2
+    /// 1F300..1F5FF ; 2
+    /// ```
+    pub fn parse(source: &str, fallback: Profile) -> Result<Self, ParseTableError> {
+        let mut table = Self::new(fallback);
+
+        for (i, line) in source.lines().enumerate() {
+            let error = ParseTableError { line: i + 1 };
+            let line = line.split('#').next().unwrap_or_default().trim();
+
+            // Nothing to do on empty lines
+            if line.is_empty() {
+                continue;
+            }
+
+            let (chars, width) = line.split_once(';').ok_or(error)?;
+            let width = width.trim().parse().map_err(|_| error)?;
+            let char = |hex: &str| {
+                u32::from_str_radix(hex.trim(), 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(error)
+            };
+
+            if let Some((start, end)) = chars.split_once("..") {
+                table.insert_range(char(start)?..=char(end)?, width);
+            } else {
+                let grapheme = chars
+                    .split_whitespace()
+                    .map(char)
+                    .collect::<Result<String, _>>()?;
+                table.insert(&grapheme, width);
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// Sets the width of `grapheme`.
+    pub fn insert(&mut self, grapheme: &str, width: usize) {
+        self.graphemes.insert(grapheme.into(), width);
+    }
+
+    /// Sets the width of graphemes starting with a character in `range`.
+    ///
+    /// Later ranges take precedence.
+    pub fn insert_range(&mut self, range: RangeInclusive<char>, width: usize) {
+        self.ranges.push((range, width));
+    }
+}
+
+impl WidthOracle for WidthTable {
+    fn grapheme_width(&self, grapheme: &str) -> usize {
+        if let Some(width) = self.graphemes.get(grapheme) {
+            return *width;
+        }
+
+        grapheme
+            .chars()
+            .next()
+            .and_then(|first| {
+                self.ranges
+                    .iter()
+                    .rev()
+                    .find(|(range, _)| range.contains(&first))
+            })
+            .map(|(_, width)| *width)
+            .unwrap_or_else(|| grapheme_width(grapheme, &self.fallback))
+    }
+}
+
+/// An error parsing a [`WidthTable`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct ParseTableError {
+    /// The line of the error, starting at 1.
+    pub line: usize,
+}
+
+impl Display for ParseTableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid width table entry at line {}", self.line)
+    }
+}
+
+impl std::error::Error for ParseTableError {}
+
 /// Unicode helpers.
 pub trait Unicode {
     type Graphemes<'a>
@@ -11,7 +354,7 @@ pub trait Unicode {
     where
         Self: 'a;
 
-    /// Returns the displayed width in columns.
+    /// Returns the displayed width in columns, with the global [`Profile`].
     fn width(&self) -> usize;
 
     /// Returns an iterator over the grapheme clusters.
@@ -26,7 +369,7 @@ impl Unicode for str {
     type Cells<'a> = Cells<'a>;
 
     fn width(&self) -> usize {
-        unicode_width::UnicodeWidthStr::width(self)
+        Profile::global().width(self)
     }
 
     fn graphemes(&self) -> Self::Graphemes<'_> {
@@ -54,14 +397,21 @@ pub mod cells {
         graphemes: Graphemes<'a>,
         index: usize,
         column: usize,
+        profile: Profile,
     }
 
     impl<'a> Cells<'a> {
         pub fn new(str: &'a str) -> Self {
+            Self::with_profile(str, &Profile::default())
+        }
+
+        /// Returns the cells of `str` measured with `profile`.
+        pub fn with_profile(str: &'a str, profile: &Profile) -> Self {
             Self {
                 graphemes: str.graphemes(),
                 index: 0,
                 column: 0,
+                profile: profile.clone(),
             }
         }
     }
@@ -73,10 +423,7 @@ pub mod cells {
             let str = self.graphemes.next()?;
             let index = self.index;
             let column = self.column;
-            let width = str.width();
-
-            // `unicode_width` has trouble with some clusters (e.g. woman scientist emoji)
-            let width = width.min(2);
+            let width = grapheme_width(str, &self.profile);
 
             self.index += str.len();
             self.column += width;
@@ -179,3 +526,78 @@ pub mod graphemes {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use test_case::test_case;
+
+    #[test_case("a"                       => 1; "ascii")]
+    #[test_case("\u{301}"                 => 0; "lone combining mark")]
+    #[test_case("e\u{301}"                => 1; "combining mark")]
+    #[test_case("🦀"                      => 2; "emoji")]
+    #[test_case("👩\u{200D}🔬"            => 2; "zwj sequence")]
+    #[test_case("\u{2764}"                => 1; "text presentation")]
+    #[test_case("\u{2764}\u{FE0F}"        => 2; "vs16")]
+    #[test_case("\u{231A}\u{FE0E}"        => 1; "vs15")]
+    #[test_case("1\u{FE0F}\u{20E3}"       => 2; "keycap")]
+    #[test_case("#\u{20E3}"               => 2; "keycap without vs16")]
+    #[test_case("a\u{FE0F}"               => 1; "vs16 without emoji variation")]
+    #[test_case("b\u{20E3}"               => 1; "keycap on a letter")]
+    #[test_case("中\u{FE0E}"              => 2; "vs15 without emoji variation")]
+    #[test_case("🇫🇷"                      => 2; "flag")]
+    #[test_case("🇫"                       => 1; "lone regional indicator")]
+    #[test_case("🏳\u{FE0F}\u{200D}🌈"    => 2; "rainbow flag")]
+    fn grapheme_width(grapheme: &str) -> usize {
+        super::grapheme_width(grapheme, &Profile::Narrow)
+    }
+
+    #[test_case("\u{2764}\u{FE0F}" => 1; "vs16")]
+    #[test_case("🇫🇷"                => 2; "flag")]
+    #[test_case("👩\u{200D}🔬"      => 2; "zwj sequence")]
+    #[test_case("e\u{301}"          => 1; "combining mark")]
+    fn legacy(grapheme: &str) -> usize {
+        super::grapheme_width(grapheme, &Profile::Legacy)
+    }
+
+    #[test]
+    fn table() {
+        let table = WidthTable::parse(
+            "# Measured\n2764 FE0F ; 1\n\n1F300..1F5FF ; 1 # Symbols\n1F5FF..1F5FF ; 2\n",
+            Profile::Narrow,
+        )
+        .unwrap();
+
+        assert_eq!(table.grapheme_width("\u{2764}\u{FE0F}"), 1);
+        assert_eq!(table.grapheme_width("🌀"), 1);
+        assert_eq!(table.grapheme_width("🗿"), 2);
+        assert_eq!(table.grapheme_width("🦀"), 2);
+        assert_eq!(table.grapheme_width("a"), 1);
+
+        let profile = Profile::custom(table.clone());
+        assert_eq!(profile, profile.clone());
+        assert_ne!(profile, Profile::custom(table));
+        assert_ne!(profile, Profile::Narrow);
+        assert_eq!(
+            HashSet::from([profile.clone(), profile.clone(), Profile::Narrow]).len(),
+            2
+        );
+        assert_eq!(profile.width("a❤️🌀"), 3);
+
+        assert_eq!(
+            WidthTable::parse("2764 ; 1\n2764 ; x", Profile::Narrow),
+            Err(ParseTableError { line: 2 })
+        );
+        assert_eq!(
+            WidthTable::parse("2764", Profile::Narrow),
+            Err(ParseTableError { line: 1 })
+        );
+    }
+
+    #[test]
+    fn width() {
+        assert_eq!(Profile::Narrow.width("a❤️🇫🇷±"), 6);
+        assert_eq!(Profile::Wide.width("a❤️🇫🇷±"), 7);
+    }
+}